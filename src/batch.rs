@@ -0,0 +1,477 @@
+use crate::blueprint::Blueprint;
+use crate::error::Result;
+use crate::output::save_image;
+use crate::render::{
+    parse_point, CameraOverrides, Projection, RenderOptions, RenderOverrides, RenderStyle,
+    Renderer, ShadowQuality, ViewPreset,
+};
+use crate::tweaks::ModelLoader;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A single entry of a batch manifest
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchJob {
+    /// An inline blueprint string
+    #[serde(default)]
+    pub blueprint: Option<String>,
+    /// A file holding the blueprint string. Only one of `blueprint` and `blueprint_file` may be
+    /// given.
+    #[serde(default)]
+    pub blueprint_file: Option<PathBuf>,
+    /// The path to save the rendered image to
+    pub output: PathBuf,
+    /// Changes to the default render options
+    #[serde(flatten)]
+    pub overrides: RenderOverrides,
+}
+
+impl BatchJob {
+    /// Get the blueprint of the job, either decoding the inline blueprint or reading the blueprint
+    /// file relative to `base_dir`.
+    pub fn read_blueprint(&self, base_dir: &Path) -> std::result::Result<Blueprint, String> {
+        match (&self.blueprint, &self.blueprint_file) {
+            (Some(blueprint), None) => Blueprint::decode_bytes(blueprint.clone().into_bytes())
+                .map_err(|err| format!("Failed to decode blueprint: {}", err)),
+            (None, Some(file)) => {
+                let path = base_dir.join(file);
+                Blueprint::read_from_file(&path)
+                    .map_err(|err| format!("{}: {}", path.display(), err))
+            }
+            (Some(_), Some(_)) => {
+                Err("Job must not specify both blueprint and blueprint_file".into())
+            }
+            (None, None) => Err("Job must specify either blueprint or blueprint_file".into()),
+        }
+    }
+}
+
+/// A row of a CSV manifest. CSV can not hold nested values, so the camera settings are given as
+/// separate columns.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CsvBatchJob {
+    #[serde(default)]
+    blueprint: Option<String>,
+    #[serde(default)]
+    blueprint_file: Option<PathBuf>,
+    output: PathBuf,
+    #[serde(default)]
+    width: Option<u32>,
+    #[serde(default)]
+    height: Option<u32>,
+    #[serde(default)]
+    ssaa: Option<u32>,
+    #[serde(default)]
+    background: Option<String>,
+    #[serde(default)]
+    ground_plane: Option<bool>,
+    #[serde(default)]
+    style: Option<RenderStyle>,
+    #[serde(default)]
+    shadows: Option<bool>,
+    #[serde(default)]
+    shadow_quality: Option<ShadowQuality>,
+    #[serde(default)]
+    ssao: Option<bool>,
+    #[serde(default)]
+    ssao_strength: Option<f32>,
+    #[serde(default)]
+    ssao_radius: Option<f32>,
+    #[serde(default)]
+    view: Option<ViewPreset>,
+    #[serde(default)]
+    yaw: Option<f32>,
+    #[serde(default)]
+    pitch: Option<f32>,
+    #[serde(default)]
+    fov: Option<f32>,
+    #[serde(default)]
+    padding: Option<f32>,
+    #[serde(default)]
+    distance: Option<f32>,
+    /// The camera target written as `x,y,z`
+    #[serde(default)]
+    target: Option<String>,
+    #[serde(default)]
+    projection: Option<Projection>,
+}
+
+impl TryFrom<CsvBatchJob> for BatchJob {
+    type Error = String;
+
+    fn try_from(job: CsvBatchJob) -> std::result::Result<Self, Self::Error> {
+        let camera = CameraOverrides {
+            view: job.view,
+            yaw: job.yaw,
+            pitch: job.pitch,
+            fov: job.fov,
+            padding: job.padding,
+            distance: job.distance,
+            target: job.target.as_deref().map(parse_point).transpose()?,
+            projection: job.projection,
+        };
+
+        let overrides = RenderOverrides {
+            width: job.width,
+            height: job.height,
+            ssaa: job.ssaa,
+            camera: Some(camera),
+            background: job.background,
+            ground_plane: job.ground_plane,
+            style: job.style,
+            shadows: job.shadows,
+            shadow_quality: job.shadow_quality,
+            ssao: job.ssao,
+            ssao_strength: job.ssao_strength,
+            ssao_radius: job.ssao_radius,
+        };
+
+        Ok(BatchJob {
+            blueprint: job.blueprint,
+            blueprint_file: job.blueprint_file,
+            output: job.output,
+            overrides,
+        })
+    }
+}
+
+/// The outcome of a single entry within a batch manifest
+#[derive(Debug, Serialize)]
+pub struct BatchJobResult {
+    /// The line (JSONL) or record (CSV) number of the job within the manifest, starting at 1
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: f64,
+}
+
+impl BatchJobResult {
+    pub fn success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// The results of every job in a batch
+#[derive(Debug, Default, Serialize)]
+pub struct BatchSummary {
+    pub jobs: Vec<BatchJobResult>,
+    pub duration_ms: f64,
+}
+
+impl BatchSummary {
+    pub fn failed(&self) -> usize {
+        self.jobs.iter().filter(|job| !job.success()).count()
+    }
+}
+
+impl Display for BatchSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for job in &self.jobs {
+            let output = job
+                .output
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default();
+
+            match &job.error {
+                None => writeln!(
+                    f,
+                    "[ OK ] line {:>4} {:>10.1}ms  {}",
+                    job.line, job.duration_ms, output
+                )?,
+                Some(err) => writeln!(
+                    f,
+                    "[FAIL] line {:>4} {:>10.1}ms  {} ({})",
+                    job.line, job.duration_ms, output, err
+                )?,
+            }
+        }
+
+        write!(
+            f,
+            "Rendered {}/{} jobs successfully in {:.1}ms",
+            self.jobs.len() - self.failed(),
+            self.jobs.len(),
+            self.duration_ms
+        )
+    }
+}
+
+/// Read the jobs from a manifest file. Files with a `.csv` extension are read as CSV with a header
+/// row naming the [`BatchJob`] fields, while all other files are read as newline delimited JSON.
+/// Within a CSV file, the camera settings are given as individual columns instead of a nested
+/// `camera` object.
+///
+/// Each job is returned alongside its line number. Entries which could not be parsed are returned
+/// as errors so they can be reported without stopping the batch.
+pub fn read_manifest<P: AsRef<Path>>(
+    path: P,
+) -> Result<Vec<(usize, std::result::Result<BatchJob, String>)>> {
+    let path = path.as_ref();
+    let is_csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+    if is_csv {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(std::io::Error::from)?;
+
+        Ok(reader
+            .deserialize::<CsvBatchJob>()
+            .enumerate()
+            .map(|(index, job)| {
+                let job = job
+                    .map_err(|err| err.to_string())
+                    .and_then(BatchJob::try_from);
+                (index + 1, job)
+            })
+            .collect())
+    } else {
+        let manifest = fs::read_to_string(path)?;
+
+        Ok(manifest
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                let job = serde_json::from_str(line).map_err(|err| err.to_string());
+                (index + 1, job)
+            })
+            .collect())
+    }
+}
+
+/// Render every job in a manifest. Failed jobs are recorded in the summary and do not stop the
+/// remaining jobs from being rendered. Relative paths within the manifest are resolved relative to
+/// the directory holding the manifest.
+pub fn run_batch<P: AsRef<Path>>(
+    manifest: P,
+    renderer: &mut dyn Renderer,
+    model_loader: &mut ModelLoader,
+    defaults: &RenderOptions,
+) -> Result<BatchSummary> {
+    let batch_start_time = Instant::now();
+    let manifest = manifest.as_ref();
+    let base_dir = manifest.parent().unwrap_or(Path::new(""));
+
+    let mut summary = BatchSummary::default();
+    for (line, job) in read_manifest(manifest)? {
+        let job_start_time = Instant::now();
+
+        let output = job.as_ref().ok().map(|job| base_dir.join(&job.output));
+        let result =
+            job.and_then(|job| render_job(job, base_dir, renderer, model_loader, defaults));
+
+        let duration = job_start_time.elapsed();
+        match &result {
+            Ok(()) => info!("Completed job on line {} in {:?}", line, duration),
+            Err(err) => warn!("Job on line {} failed: {}", line, err),
+        }
+
+        summary.jobs.push(BatchJobResult {
+            line,
+            output,
+            error: result.err(),
+            duration_ms: as_millis(duration),
+        });
+    }
+
+    summary.duration_ms = as_millis(batch_start_time.elapsed());
+    Ok(summary)
+}
+
+fn render_job(
+    job: BatchJob,
+    base_dir: &Path,
+    renderer: &mut dyn Renderer,
+    model_loader: &mut ModelLoader,
+    defaults: &RenderOptions,
+) -> std::result::Result<(), String> {
+    let blueprint = job.read_blueprint(base_dir)?;
+    let options = job
+        .overrides
+        .apply(defaults)
+        .map_err(|err| err.to_string())?;
+
+    let img = renderer
+        .render(&blueprint, model_loader, &options)
+        .map_err(|err| err.to_string())?;
+    save_image(img, base_dir.join(job.output)).map_err(|err| err.to_string())
+}
+
+fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{SoftwareRenderer, ViewPreset};
+
+    const BELT: &str =
+        r#"{"V":1122,"BP":{"Entries":[{"X":0,"Y":0,"T":"BeltDefaultForwardInternalVariant"}]}}"#;
+
+    /// Create an empty directory holding a blueprint file named `belt.txt`
+    fn batch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("shapez2-batch-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let blueprint = Blueprint::from_json(BELT.as_bytes()).unwrap();
+        fs::write(dir.join("belt.txt"), blueprint.encode()).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reads_jsonl_manifest() {
+        let dir = batch_dir("jsonl");
+        let manifest = dir.join("manifest.jsonl");
+        fs::write(
+            &manifest,
+            [
+                r#"{"blueprint_file": "belt.txt", "output": "belt.png", "width": 64, "camera": {"view": "top"}}"#,
+                "",
+                r#"{"blueprint_file": "belt.txt", "output": "belt.png", "size": 64}"#,
+                r#"{"blueprint_file": "belt.txt""#,
+                r#"{"blueprint_file": "belt.txt", "output": "belt.png", "style": "sketch"}"#,
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        let jobs = read_manifest(&manifest).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        let lines: Vec<usize> = jobs.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [1, 3, 4, 5]);
+
+        let job = jobs[0].1.as_ref().unwrap();
+        assert_eq!(job.blueprint_file, Some(PathBuf::from("belt.txt")));
+        assert_eq!(job.output, PathBuf::from("belt.png"));
+        assert_eq!(job.overrides.width, Some(64));
+        assert_eq!(job.overrides.height, None);
+        assert_eq!(
+            job.overrides.camera.as_ref().unwrap().view,
+            Some(ViewPreset::Top)
+        );
+
+        assert!(jobs[1..].iter().all(|(_, job)| job.is_err()));
+    }
+
+    #[test]
+    fn reads_csv_manifest() {
+        let dir = batch_dir("csv");
+        let manifest = dir.join("manifest.CSV");
+        fs::write(
+            &manifest,
+            "blueprint_file,output,width,style,view,target,projection\n\
+             belt.txt, belt.png ,64,realistic,iso,\"1,2,3\",ortho\n\
+             belt.txt,empty.png,,,,,\n\
+             belt.txt,wide.png,wide,,,,\n\
+             belt.txt,sketch.png,,sketch,,,\n\
+             belt.txt,target.png,,,,\"1,2\",\n",
+        )
+        .unwrap();
+
+        let jobs = read_manifest(&manifest).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        let lines: Vec<usize> = jobs.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [1, 2, 3, 4, 5]);
+
+        let job = jobs[0].1.as_ref().unwrap();
+        assert_eq!(job.output, PathBuf::from("belt.png"));
+        assert_eq!(job.overrides.width, Some(64));
+        assert_eq!(job.overrides.style, Some(RenderStyle::Realistic));
+        let camera = job.overrides.camera.as_ref().unwrap();
+        assert_eq!(camera.view, Some(ViewPreset::Isometric));
+        assert_eq!(camera.target, Some([1.0, 2.0, 3.0]));
+        assert_eq!(camera.projection, Some(Projection::Orthographic));
+
+        // Empty cells keep the default options
+        let job = jobs[1].1.as_ref().unwrap();
+        assert_eq!(job.overrides.width, None);
+        assert_eq!(job.overrides.style, None);
+        assert_eq!(job.overrides.camera.as_ref().unwrap().target, None);
+
+        assert!(jobs[2..].iter().all(|(_, job)| job.is_err()));
+    }
+
+    #[test]
+    fn renders_jobs_relative_to_manifest() {
+        let dir = batch_dir("render");
+        let manifest = dir.join("manifest.jsonl");
+        let inline = Blueprint::from_json(BELT.as_bytes()).unwrap().encode();
+        fs::write(
+            &manifest,
+            [
+                r#"{"blueprint_file": "belt.txt", "output": "belt.png", "width": 48, "height": 32}"#
+                    .to_string(),
+                format!(r#"{{"blueprint": "{}", "output": "inline.png", "width": 0}}"#, inline),
+                format!(
+                    r#"{{"blueprint": "{}", "blueprint_file": "belt.txt", "output": "both.png"}}"#,
+                    inline
+                ),
+                r#"{"blueprint_file": "missing.txt", "output": "missing.png"}"#.to_string(),
+                r#"{"output": "none.png"}"#.to_string(),
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        let mut model_loader =
+            ModelLoader::new(concat!(env!("CARGO_MANIFEST_DIR"), "/models")).unwrap();
+        let defaults = RenderOptions {
+            width: 32,
+            height: 32,
+            ..RenderOptions::default()
+        };
+        let summary = run_batch(
+            &manifest,
+            &mut SoftwareRenderer::new(),
+            &mut model_loader,
+            &defaults,
+        )
+        .unwrap();
+
+        let rendered = image::open(dir.join("belt.png")).map(|img| (img.width(), img.height()));
+        let others_exist = ["inline.png", "both.png", "missing.png", "none.png"]
+            .map(|name| dir.join(name).exists());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(rendered.unwrap(), (48, 32));
+        assert_eq!(others_exist, [false; 4]);
+
+        let results: Vec<(usize, bool)> = summary
+            .jobs
+            .iter()
+            .map(|job| (job.line, job.success()))
+            .collect();
+        assert_eq!(
+            results,
+            [(1, true), (2, false), (3, false), (4, false), (5, false)]
+        );
+        assert_eq!(summary.jobs[0].output, Some(dir.join("belt.png")));
+        assert_eq!(
+            summary.jobs[2].error.as_deref(),
+            Some("Job must not specify both blueprint and blueprint_file")
+        );
+        assert!(summary.jobs[3]
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with(&dir.join("missing.txt").display().to_string()));
+        assert_eq!(summary.failed(), 4);
+    }
+}
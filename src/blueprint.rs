@@ -1,80 +1,404 @@
+use crate::building_config::BuildingConfig;
+use crate::error::{Error, Result};
+use crate::footprint::Footprint;
 use base64::prelude::BASE64_STANDARD;
-use base64::read::DecoderReader;
-use flate2::read::GzDecoder;
-use log::error;
+use base64::Engine;
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use nalgebra_glm::Vec3;
 use num_traits::FloatConst;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io;
-use std::io::ErrorKind::InvalidData;
-use std::io::{stdin, BufReader, Error, Read};
+use std::io::{stdin, BufReader, Read, Write};
 use std::ops::Deref;
 use std::path::Path;
-use std::process::exit;
+use std::string::FromUtf8Error;
 
-#[derive(Serialize, Deserialize)]
+const BLUEPRINT_PREFIX: &str = "SHAPEZ2-1-";
+const BLUEPRINT_SUFFIX: char = '$';
+
+const BUILDING_BLUEPRINT_TYPE: &str = "Building";
+const ISLAND_BLUEPRINT_TYPE: &str = "Island";
+
+/// Buildings whose mirrored counterpart cannot be found by swapping left and right in their name
+const MIRRORED_BUILDINGS: [(&str, &str); 1] = [("RotatorOneQuad", "RotatorOneQuadCCW")];
+
+/// The number of tiles along each side of an island chunk
+pub const ISLAND_CHUNK_SIZE: i32 = 20;
+
+/// A decoded blueprint. Dereferencing a blueprint gives every building within it. For island
+/// blueprints, the buildings of each island are moved into the space of the blueprint.
+#[derive(Serialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct Blueprint {
     v: i32,
-    bp: BlueprintEntries,
+    bp: BlueprintContents,
+    /// Fields which are not used by the renderer, such as the icon of the blueprint. These are
+    /// kept so they are written back out when the blueprint is encoded.
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+    #[serde(skip)]
+    island_buildings: Vec<BlueprintEntry>,
+}
+
+/// The entries of a blueprint, which are either buildings or islands
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum BlueprintContents {
+    Buildings(BlueprintEntries),
+    Islands(IslandEntries),
+}
+
+/// The part of a blueprint read to determine its type before it is fully parsed
+#[derive(Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct BlueprintHeader {
+    bp: BlueprintTypeHeader,
+}
+
+#[derive(Deserialize)]
+struct BlueprintTypeHeader {
+    #[serde(rename = "$type", default)]
+    kind: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct BlueprintDocument<T> {
+    v: i32,
+    bp: T,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
 }
 
 impl Blueprint {
-    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Self {
-        let mut file = match File::open(path) {
-            Ok(v) => BufReader::new(v),
-            Err(e) => {
-                error!("unable to open input file: {}", e);
-                exit(1);
-            }
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        Self::decode(&mut file)
+    }
+
+    pub fn read_from_stdin() -> Result<Self> {
+        Self::decode(&mut stdin())
+    }
+
+    pub fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut data = Vec::new();
+        io::copy(reader, &mut data)?;
+
+        Ok(Self::decode_bytes(data)?)
+    }
+
+    /// Decode a blueprint string. Blueprints are stored as gzipped JSON which has been base64
+    /// encoded and wrapped with a prefix and suffix.
+    pub fn decode_bytes(data: Vec<u8>) -> std::result::Result<Self, DecodeError> {
+        let utf8 = String::from_utf8(data).map_err(DecodeError::Utf8)?;
+
+        let start = utf8.len() - utf8.trim_start().len();
+        let trimmed = utf8.trim();
+
+        let Some(body) = trimmed.strip_prefix(BLUEPRINT_PREFIX) else {
+            let matching = trimmed
+                .bytes()
+                .zip(BLUEPRINT_PREFIX.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            return Err(DecodeError::MissingPrefix {
+                offset: start + matching,
+            });
+        };
+        let start = start + BLUEPRINT_PREFIX.len();
+
+        let Some(body) = body.strip_suffix(BLUEPRINT_SUFFIX) else {
+            return Err(DecodeError::MissingSuffix {
+                offset: start + body.len(),
+            });
         };
 
-        match Self::decode(&mut file) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("Failed to parse blueprint: {}", e);
-                exit(1);
+        let compressed = BASE64_STANDARD.decode(body).map_err(|source| {
+            let offset = match source {
+                base64::DecodeError::InvalidByte(offset, _)
+                | base64::DecodeError::InvalidLastSymbol(offset, _) => offset,
+                base64::DecodeError::InvalidLength | base64::DecodeError::InvalidPadding => {
+                    body.len()
+                }
+            };
+            DecodeError::Base64 {
+                source,
+                offset: start + offset,
             }
+        })?;
+
+        let mut json = Vec::new();
+        let mut decoder = GzDecoder::new(&compressed[..]);
+        if let Err(source) = decoder.read_to_end(&mut json) {
+            return Err(DecodeError::Gzip {
+                source,
+                offset: compressed.len() - decoder.get_ref().len(),
+            });
         }
+
+        Self::from_json(&json)
+    }
+
+    /// Encode the blueprint as a blueprint string which can be pasted into the game. This is the
+    /// inverse of [`Blueprint::decode_bytes`].
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("blueprints can always be serialized");
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let compressed = encoder
+            .write_all(&json)
+            .and_then(|_| encoder.finish())
+            .expect("compressing into memory does not fail");
+
+        format!(
+            "{}{}{}",
+            BLUEPRINT_PREFIX,
+            BASE64_STANDARD.encode(compressed),
+            BLUEPRINT_SUFFIX
+        )
     }
 
-    pub fn read_from_stdin() -> Self {
-        let mut stdin = stdin();
+    /// Parse the JSON document held within a blueprint string.
+    pub fn from_json(json: &[u8]) -> std::result::Result<Self, DecodeError> {
+        let json_error = |source| DecodeError::json(source, json);
+        let header: BlueprintHeader = serde_json::from_slice(json).map_err(json_error)?;
+
+        match header.bp.kind.as_deref() {
+            None | Some(BUILDING_BLUEPRINT_TYPE) => {
+                let document: BlueprintDocument<BlueprintEntries> =
+                    serde_json::from_slice(json).map_err(json_error)?;
 
-        match Self::decode(&mut stdin) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("Failed to parse blueprint: {}", e);
-                exit(1);
+                Ok(Blueprint {
+                    v: document.v,
+                    bp: BlueprintContents::Buildings(document.bp),
+                    extra: document.extra,
+                    island_buildings: Vec::new(),
+                })
             }
+            Some(ISLAND_BLUEPRINT_TYPE) => {
+                let document: BlueprintDocument<IslandEntries> =
+                    serde_json::from_slice(json).map_err(json_error)?;
+
+                Ok(Blueprint {
+                    v: document.v,
+                    island_buildings: document.bp.place_buildings(),
+                    bp: BlueprintContents::Islands(document.bp),
+                    extra: document.extra,
+                })
+            }
+            Some(kind) => Err(DecodeError::UnknownType(kind.to_string())),
         }
     }
 
-    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
-        let mut data = Vec::new();
-        io::copy(reader, &mut data)?;
+    /// Get the version of the game the blueprint was created with
+    pub fn version(&self) -> i32 {
+        self.v
+    }
 
-        let utf8 = String::from_utf8(data)
-            .map_err(|_| Error::new(InvalidData, "Blueprint must be utf-8"))?;
+    pub fn contents(&self) -> &BlueprintContents {
+        &self.bp
+    }
 
-        let mut trimmed = utf8.as_str().trim();
-        trimmed = trimmed.strip_prefix("SHAPEZ2-1-").ok_or_else(|| {
-            Error::new(InvalidData, "Expected blueprint to start with 'SHAPEZ2-1-'")
-        })?;
-        trimmed = trimmed
-            .strip_suffix('$')
-            .ok_or_else(|| Error::new(InvalidData, "Expected blueprint to end with '$'"))?;
-
-        let mut reader = trimmed.as_bytes();
-        let decoder = DecoderReader::new(&mut reader, &BASE64_STANDARD);
-        let deflate = GzDecoder::new(decoder);
-
-        serde_json::from_reader(deflate)
-            .map_err(|err| {
-                error!("Error occurred during blueprint read pipeline (Base64 decode -> Gunzip -> Json parse): {}", err);
-                Error::new(InvalidData, "Unable to decode blueprint data")
-            })
+    /// Rotate the blueprint clockwise by a number of quarter turns about the origin tile. This is the
+    /// same direction buildings are turned by their rotation.
+    pub fn rotate(&mut self, quarter_turns: i32) -> Result<()> {
+        for entry in self.entries_mut()? {
+            entry.rotate(quarter_turns);
+        }
+        Ok(())
+    }
+
+    /// Mirror the blueprint by negating the coordinate along an axis. Buildings with a left and
+    /// right variant, such as turning belts, are replaced by the opposite variant.
+    pub fn mirror(&mut self, axis: Axis) -> Result<()> {
+        for entry in self.entries_mut()? {
+            entry.mirror(axis);
+        }
+        Ok(())
+    }
+
+    /// Move every building in the blueprint by an offset in tiles.
+    pub fn translate(&mut self, x: i32, y: i32, layer: i32) -> Result<()> {
+        for entry in self.entries_mut()? {
+            entry.translate(x, y, layer);
+        }
+        Ok(())
+    }
+
+    /// Move the blueprint so that the minimum x and y tile covered by its buildings is at the
+    /// origin. The layers of the buildings are left unchanged.
+    pub fn normalize(&mut self) -> Result<()> {
+        let entries = self.entries_mut()?;
+        let min_x = entries.iter().map(|entry| entry.bounds().0 .0).min();
+        let min_y = entries.iter().map(|entry| entry.bounds().0 .1).min();
+        let (min_x, min_y) = (min_x.unwrap_or(0), min_y.unwrap_or(0));
+
+        for entry in entries {
+            entry.translate(-min_x, -min_y, 0);
+        }
+        Ok(())
+    }
+
+    /// Get the buildings of a building blueprint to transform them. Island blueprints cannot be
+    /// transformed, as each island is laid out in chunks rather than tiles.
+    fn entries_mut(&mut self) -> Result<&mut [BlueprintEntry]> {
+        match &mut self.bp {
+            BlueprintContents::Buildings(buildings) => Ok(&mut buildings.entries),
+            BlueprintContents::Islands(_) => Err(Error::Transform(
+                "island blueprints can not be transformed".to_string(),
+            )),
+        }
+    }
+
+    /// Get the islands of an island blueprint. Building blueprints do not have any islands.
+    pub fn islands(&self) -> &[IslandEntry] {
+        match &self.bp {
+            BlueprintContents::Buildings(_) => &[],
+            BlueprintContents::Islands(islands) => &islands.entries,
+        }
+    }
+}
+
+/// The stages of the blueprint decode pipeline
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeStage {
+    /// Reading the text of the blueprint string and removing its prefix and suffix
+    Prefix,
+    /// Decoding the base64 body of the blueprint string
+    Base64,
+    /// Decompressing the gzipped data held within the body
+    Gzip,
+    /// Parsing the decompressed JSON document
+    Json,
+}
+
+impl DecodeStage {
+    /// Describe the data which offsets reported for this stage point into.
+    pub fn offset_context(&self) -> &'static str {
+        match self {
+            DecodeStage::Prefix | DecodeStage::Base64 => "blueprint string",
+            DecodeStage::Gzip => "compressed data",
+            DecodeStage::Json => "JSON document",
+        }
+    }
+}
+
+impl Display for DecodeStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DecodeStage::Prefix => "prefix",
+            DecodeStage::Base64 => "base64",
+            DecodeStage::Gzip => "gzip",
+            DecodeStage::Json => "JSON",
+        })
+    }
+}
+
+/// The stage of the blueprint decode pipeline (prefix -> base64 -> gzip -> JSON) which failed
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The blueprint was not valid UTF-8
+    Utf8(FromUtf8Error),
+    /// The blueprint did not start with the expected prefix. The offset is that of the first byte
+    /// which differs from the prefix.
+    MissingPrefix { offset: usize },
+    /// The blueprint did not end with the expected suffix
+    MissingSuffix { offset: usize },
+    /// The blueprint body was not valid base64
+    Base64 {
+        source: base64::DecodeError,
+        offset: usize,
+    },
+    /// The decoded data was not valid gzip. The offset is how far into the compressed data the
+    /// decompressor had read when it failed.
+    Gzip { source: io::Error, offset: usize },
+    /// The decompressed data was not a valid blueprint JSON document
+    Json {
+        source: serde_json::Error,
+        offset: Option<usize>,
+    },
+    /// The blueprint has a `$type` which is not supported
+    UnknownType(String),
+}
+
+impl DecodeError {
+    fn json(source: serde_json::Error, json: &[u8]) -> Self {
+        // serde_json reports lines and columns starting from 1, with a line of 0 when the error
+        // does not refer to a position within the document
+        let offset = match source.line() {
+            0 => None,
+            line => json
+                .split_inclusive(|&byte| byte == b'\n')
+                .take(line - 1)
+                .map(<[u8]>::len)
+                .sum::<usize>()
+                .checked_add(source.column().saturating_sub(1)),
+        };
+
+        DecodeError::Json { source, offset }
+    }
+
+    /// Get the stage of the decode pipeline which failed
+    pub fn stage(&self) -> DecodeStage {
+        match self {
+            DecodeError::Utf8(_)
+            | DecodeError::MissingPrefix { .. }
+            | DecodeError::MissingSuffix { .. } => DecodeStage::Prefix,
+            DecodeError::Base64 { .. } => DecodeStage::Base64,
+            DecodeError::Gzip { .. } => DecodeStage::Gzip,
+            DecodeError::Json { .. } | DecodeError::UnknownType(_) => DecodeStage::Json,
+        }
+    }
+
+    /// Get the byte offset at which decoding failed, if known. The offset points into the data read
+    /// by the failing stage, as described by [`DecodeStage::offset_context`].
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            DecodeError::Utf8(err) => Some(err.utf8_error().valid_up_to()),
+            DecodeError::MissingPrefix { offset }
+            | DecodeError::MissingSuffix { offset }
+            | DecodeError::Base64 { offset, .. }
+            | DecodeError::Gzip { offset, .. } => Some(*offset),
+            DecodeError::Json { offset, .. } => *offset,
+            DecodeError::UnknownType(_) => None,
+        }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Utf8(err) => write!(f, "blueprint must be utf-8: {}", err),
+            DecodeError::MissingPrefix { .. } => {
+                write!(f, "expected blueprint to start with '{}'", BLUEPRINT_PREFIX)
+            }
+            DecodeError::MissingSuffix { .. } => {
+                write!(f, "expected blueprint to end with '{}'", BLUEPRINT_SUFFIX)
+            }
+            DecodeError::Base64 { source, .. } => write!(f, "base64 decode failed: {}", source),
+            DecodeError::Gzip { source, .. } => write!(f, "gzip decompression failed: {}", source),
+            DecodeError::Json { source, .. } => write!(f, "JSON parse failed: {}", source),
+            DecodeError::UnknownType(kind) => write!(f, "unknown blueprint type '{}'", kind),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::Utf8(err) => Some(err),
+            DecodeError::Base64 { source, .. } => Some(source),
+            DecodeError::Gzip { source, .. } => Some(source),
+            DecodeError::Json { source, .. } => Some(source),
+            DecodeError::MissingPrefix { .. }
+            | DecodeError::MissingSuffix { .. }
+            | DecodeError::UnknownType(_) => None,
+        }
     }
 }
 
@@ -82,43 +406,499 @@ impl Deref for Blueprint {
     type Target = [BlueprintEntry];
 
     fn deref(&self) -> &Self::Target {
-        &self.bp.entries
+        match &self.bp {
+            BlueprintContents::Buildings(buildings) => &buildings.entries,
+            BlueprintContents::Islands(_) => &self.island_buildings,
+        }
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct BlueprintEntries {
+    #[serde(rename = "$type", default, skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
     #[serde(rename = "Entries")]
     entries: Vec<BlueprintEntry>,
+    /// Fields which are not used by the renderer, such as the icon of the blueprint. These are
+    /// kept so they are written back out when the blueprint is encoded.
+    #[serde(flatten)]
+    extra: Map<String, Value>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl BlueprintEntries {
+    pub fn entries(&self) -> &[BlueprintEntry] {
+        &self.entries
+    }
+}
+
+/// A building within a blueprint. The layer, rotation and settings of a building may be left out,
+/// in which case they are also left out when the entry is encoded.
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct BlueprintEntry {
     x: i32,
     y: i32,
-    #[serde(rename = "L")]
-    layer: i32,
-    #[serde(rename = "R")]
-    rotation: i32,
+    #[serde(rename = "L", default, skip_serializing_if = "Option::is_none")]
+    layer: Option<i32>,
+    #[serde(rename = "R", default, skip_serializing_if = "Option::is_none")]
+    rotation: Option<i32>,
     #[serde(rename = "T")]
     internal_name: String,
-    #[serde(rename = "C")]
-    attached_data: String,
+    #[serde(rename = "C", default, skip_serializing_if = "Option::is_none")]
+    attached_data: Option<String>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
 }
 
 impl BlueprintEntry {
     /// Get the position with layer mapped to the Y axis.
     pub fn position(&self) -> Vec3 {
-        Vec3::new(self.x as f32, self.layer as f32, self.y as f32)
+        Vec3::new(self.x as f32, self.layer() as f32, self.y as f32)
     }
 
     /// Get the rotation of this entry in radians
     pub fn rotation(&self) -> f32 {
-        self.rotation as f32 * f32::PI() / 2.0
+        self.raw_rotation() as f32 * f32::PI() / 2.0
+    }
+
+    /// Get the tile position of this entry as (x, y, layer)
+    pub fn tile(&self) -> (i32, i32, i32) {
+        (self.x, self.y, self.layer())
+    }
+
+    fn layer(&self) -> i32 {
+        self.layer.unwrap_or(0)
+    }
+
+    /// Get the tiles and layers covered by this building before it is rotated
+    pub fn footprint(&self) -> Footprint {
+        Footprint::for_building(&self.internal_name)
+    }
+
+    /// Get every (x, y, layer) tile covered by this building
+    pub fn tiles(&self) -> Vec<(i32, i32, i32)> {
+        self.footprint()
+            .tiles(self.raw_rotation())
+            .into_iter()
+            .map(|(x, y, layer)| (self.x + x, self.y + y, self.layer() + layer))
+            .collect()
+    }
+
+    /// Get the smallest and largest (x, y, layer) tiles covered by this building
+    pub fn bounds(&self) -> ((i32, i32, i32), (i32, i32, i32)) {
+        let ((min_x, min_y, min_layer), (max_x, max_y, max_layer)) =
+            self.footprint().bounds(self.raw_rotation());
+        (
+            (self.x + min_x, self.y + min_y, self.layer() + min_layer),
+            (self.x + max_x, self.y + max_y, self.layer() + max_layer),
+        )
+    }
+
+    /// Get the rotation of this entry in quarter turns
+    pub fn quarter_turns(&self) -> i32 {
+        self.raw_rotation().rem_euclid(4)
+    }
+
+    /// Get the rotation as it is stored in the blueprint, which may lie outside of 0 to 3
+    pub fn raw_rotation(&self) -> i32 {
+        self.rotation.unwrap_or(0)
     }
 
     pub fn internal_name(&self) -> &str {
         &self.internal_name
     }
+
+    /// Rotate the entry clockwise by a number of quarter turns about the origin tile.
+    pub fn rotate(&mut self, quarter_turns: i32) {
+        for _ in 0..quarter_turns.rem_euclid(4) {
+            (self.x, self.y) = (-self.y, self.x);
+        }
+        let rotation = (self.raw_rotation() + quarter_turns).rem_euclid(4);
+        set_field(&mut self.rotation, rotation);
+    }
+
+    /// Mirror the entry by negating the coordinate along an axis. The building is replaced by its
+    /// mirrored variant when it has one, and is moved so that it covers the mirror image of the
+    /// tiles it covered before.
+    pub fn mirror(&mut self, axis: Axis) {
+        let ((min_x, min_y, _), (max_x, max_y, _)) = self.bounds();
+        let (expected_x, expected_y) = match axis {
+            Axis::X => (-max_x, min_y),
+            Axis::Y => (min_x, -max_y),
+        };
+
+        // Buildings point along the x axis with an even rotation and along the y axis with an odd
+        // rotation, so only the rotations pointing along the mirrored axis are reversed
+        let rotation = match axis {
+            Axis::X => {
+                self.x = -self.x;
+                2 - self.raw_rotation()
+            }
+            Axis::Y => {
+                self.y = -self.y;
+                -self.raw_rotation()
+            }
+        };
+        set_field(&mut self.rotation, rotation.rem_euclid(4));
+        self.internal_name = mirrored_internal_name(&self.internal_name);
+
+        let ((min_x, min_y, _), _) = self.bounds();
+        self.translate(expected_x - min_x, expected_y - min_y, 0);
+    }
+
+    /// Move the entry by an offset in tiles.
+    pub fn translate(&mut self, x: i32, y: i32, layer: i32) {
+        self.x += x;
+        self.y += y;
+        let layer = self.layer() + layer;
+        set_field(&mut self.layer, layer);
+    }
+
+    /// Get the undecoded settings attached to this entry
+    pub fn attached_data(&self) -> &str {
+        self.attached_data.as_deref().unwrap_or_default()
+    }
+
+    /// Decode the settings attached to this entry, such as the text of a label.
+    pub fn config(&self) -> BuildingConfig {
+        BuildingConfig::decode(&self.internal_name, self.attached_data())
+    }
+}
+
+/// Set a field which may be left out of a blueprint. Fields which were left out stay left out
+/// while they hold the default value.
+fn set_field<T: Default + PartialEq>(field: &mut Option<T>, value: T) {
+    if field.is_some() || value != T::default() {
+        *field = Some(value);
+    }
+}
+
+/// An axis of a blueprint, used to mirror it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+/// Get the internal name of the mirrored variant of a building. Left and right are swapped, both
+/// when written out (`BeltDefaultLeftInternalVariant`) and when written as a single letter after a
+/// number (`Splitter1To2LInternalVariant`), and clockwise rotators are swapped with counterclockwise
+/// ones. Buildings without a mirrored variant keep their name.
+pub fn mirrored_internal_name(internal_name: &str) -> String {
+    let (base, suffix) = match internal_name.strip_suffix("InternalVariant") {
+        Some(base) => (base, "InternalVariant"),
+        None => (internal_name, ""),
+    };
+
+    for (a, b) in MIRRORED_BUILDINGS {
+        if base == a {
+            return format!("{}{}", b, suffix);
+        }
+        if base == b {
+            return format!("{}{}", a, suffix);
+        }
+    }
+
+    let mirrored = if base.contains("Left") || base.contains("Right") {
+        base.split("Left")
+            .map(|part| part.replace("Right", "Left"))
+            .collect::<Vec<_>>()
+            .join("Right")
+    } else {
+        let mut chars: Vec<char> = base.chars().collect();
+        if let [.., digit, side @ ('L' | 'R')] = chars.as_mut_slice() {
+            if digit.is_ascii_digit() {
+                *side = if *side == 'L' { 'R' } else { 'L' };
+            }
+        }
+        chars.into_iter().collect()
+    };
+
+    format!("{}{}", mirrored, suffix)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IslandEntries {
+    #[serde(rename = "$type", default, skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+    #[serde(rename = "Entries")]
+    entries: Vec<IslandEntry>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl IslandEntries {
+    /// Move the buildings of every island into the space of the blueprint
+    fn place_buildings(&self) -> Vec<BlueprintEntry> {
+        self.entries
+            .iter()
+            .flat_map(|island| {
+                island
+                    .buildings()
+                    .iter()
+                    .map(move |entry| island.place_building(entry))
+            })
+            .collect()
+    }
+}
+
+/// An island (space platform) within an island blueprint.
+///
+/// Islands are positioned in chunks of [`ISLAND_CHUNK_SIZE`] tiles. Within an island, the chunk at
+/// the origin covers the tiles from -10 to 9 on both axes, and rotating the island turns it about
+/// the center of that chunk.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct IslandEntry {
+    x: i32,
+    y: i32,
+    #[serde(rename = "R", default)]
+    rotation: i32,
+    #[serde(rename = "T")]
+    layout: String,
+    #[serde(rename = "B", default, skip_serializing_if = "Option::is_none")]
+    buildings: Option<BlueprintEntries>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl IslandEntry {
+    /// Get the position of the island in chunks
+    pub fn chunk_position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    /// Get the rotation of the island in quarter turns
+    pub fn quarter_turns(&self) -> i32 {
+        self.rotation.rem_euclid(4)
+    }
+
+    /// Get the name of the layout describing the shape of the island, such as `Layout_1`
+    pub fn layout(&self) -> &str {
+        &self.layout
+    }
+
+    /// Get the buildings on the island, positioned relative to the island
+    pub fn buildings(&self) -> &[BlueprintEntry] {
+        self.buildings
+            .as_ref()
+            .map_or(&[], |buildings| &buildings.entries)
+    }
+
+    /// Rotate a tile position within the island by the rotation of the island.
+    pub fn rotate_tile(&self, (mut x, mut y): (i32, i32)) -> (i32, i32) {
+        for _ in 0..self.quarter_turns() {
+            (x, y) = (-y - 1, x);
+        }
+        (x, y)
+    }
+
+    /// Move a building on this island into the space of the blueprint.
+    pub fn place_building(&self, entry: &BlueprintEntry) -> BlueprintEntry {
+        let (x, y) = self.rotate_tile((entry.x, entry.y));
+
+        BlueprintEntry {
+            x: x + self.x * ISLAND_CHUNK_SIZE,
+            y: y + self.y * ISLAND_CHUNK_SIZE,
+            rotation: Some(entry.raw_rotation() + self.rotation),
+            ..entry.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The blueprint shown in the README, as copied from the game
+    const README_BLUEPRINT: &str = include_str!("../tests/blueprints/readme.txt");
+
+    fn decode_json(blueprint: &str) -> Value {
+        let compressed = BASE64_STANDARD
+            .decode(
+                blueprint
+                    .trim()
+                    .strip_prefix(BLUEPRINT_PREFIX)
+                    .and_then(|body| body.strip_suffix(BLUEPRINT_SUFFIX))
+                    .unwrap(),
+            )
+            .unwrap();
+
+        let mut json = Vec::new();
+        GzDecoder::new(&compressed[..])
+            .read_to_end(&mut json)
+            .unwrap();
+        serde_json::from_slice(&json).unwrap()
+    }
+
+    /// Create a building blueprint from its entries written as JSON
+    fn blueprint(entries: &str) -> Blueprint {
+        let json = format!(r#"{{"V":1122,"BP":{{"Entries":{}}}}}"#, entries);
+        Blueprint::from_json(json.as_bytes()).unwrap()
+    }
+
+    fn sorted_tiles(entry: &BlueprintEntry) -> Vec<(i32, i32, i32)> {
+        let mut tiles = entry.tiles();
+        tiles.sort();
+        tiles
+    }
+
+    #[test]
+    fn round_trip_preserves_document() {
+        let blueprint = Blueprint::decode_bytes(README_BLUEPRINT.as_bytes().to_vec()).unwrap();
+        assert_eq!(blueprint.len(), 210);
+
+        let encoded = blueprint.encode();
+        assert_eq!(decode_json(&encoded), decode_json(README_BLUEPRINT));
+    }
+
+    #[test]
+    fn round_trip_preserves_unknown_fields() {
+        let json = serde_json::json!({
+            "V": 1122,
+            "BP": {
+                "$type": "Building",
+                "Icon": {
+                    "Data": ["icon:Buildings", null, null, "shape:CuCuCuCu"]
+                },
+                "Entries": [
+                    {
+                        "X": 0,
+                        "Y": 0,
+                        "L": 0,
+                        "R": 0,
+                        "T": "BeltDefaultForwardInternalVariant",
+                        "C": ""
+                    },
+                    {
+                        "X": 1,
+                        "Y": 0,
+                        "L": 0,
+                        "R": 1,
+                        "T": "LabelDefaultInternalVariant",
+                        "C": "BQBIRUxMTw==",
+                        "Unknown": [1, 2]
+                    },
+                    {
+                        "X": 2,
+                        "Y": -1,
+                        "T": "CutterDefaultInternalVariant"
+                    },
+                    {
+                        "X": 3,
+                        "Y": 0,
+                        "R": 2,
+                        "T": "BeltDefaultLeftInternalVariant"
+                    }
+                ],
+                "BinaryVersion": 1122
+            }
+        });
+
+        let blueprint = Blueprint::from_json(json.to_string().as_bytes()).unwrap();
+        assert_eq!(decode_json(&blueprint.encode()), json);
+    }
+
+    #[test]
+    fn omitted_fields_are_only_added_when_changed() {
+        let json = r#"{"X":0,"Y":0,"T":"BeltDefaultForwardInternalVariant"}"#;
+        let encode = |entry: &BlueprintEntry| serde_json::to_value(entry).unwrap();
+
+        let mut entry: BlueprintEntry = serde_json::from_str(json).unwrap();
+        entry.rotate(4);
+        entry.translate(1, 0, 0);
+        assert_eq!(
+            encode(&entry),
+            serde_json::json!({"X": 1, "Y": 0, "T": "BeltDefaultForwardInternalVariant"})
+        );
+
+        entry.rotate(1);
+        entry.translate(0, 0, 1);
+        assert_eq!(
+            encode(&entry),
+            serde_json::json!({"X": 0, "Y": 1, "L": 1, "R": 1, "T": "BeltDefaultForwardInternalVariant"})
+        );
+    }
+
+    #[test]
+    fn rotating_moves_every_tile_of_a_building() {
+        const CUTTER: &str = r#"[{"X":1,"Y":0,"L":1,"T":"CutterDefaultInternalVariant"}]"#;
+        let original = blueprint(CUTTER);
+        assert_eq!(sorted_tiles(&original[0]), [(1, -1, 1), (1, 0, 1)]);
+
+        // Each quarter turn moves a tile from (x, y) to (-y, x)
+        let expected = [
+            [(1, -1, 1), (1, 0, 1)],
+            [(0, 1, 1), (1, 1, 1)],
+            [(-1, 0, 1), (-1, 1, 1)],
+            [(-1, -1, 1), (0, -1, 1)],
+        ];
+        for (quarter_turns, expected) in expected.into_iter().enumerate() {
+            let mut rotated = blueprint(CUTTER);
+            rotated.rotate(quarter_turns as i32).unwrap();
+
+            assert_eq!(rotated[0].quarter_turns(), quarter_turns as i32);
+            assert_eq!(sorted_tiles(&rotated[0]), expected);
+        }
+
+        let mut rotated = blueprint(CUTTER);
+        rotated.rotate(-1).unwrap();
+        assert_eq!(sorted_tiles(&rotated[0]), expected[3]);
+        assert_eq!(rotated[0].tile(), (0, -1, 1));
+    }
+
+    #[test]
+    fn mirroring_swaps_rotator_directions() {
+        assert_eq!(
+            mirrored_internal_name("RotatorOneQuadInternalVariant"),
+            "RotatorOneQuadCCWInternalVariant"
+        );
+        assert_eq!(
+            mirrored_internal_name("RotatorOneQuadCCWInternalVariant"),
+            "RotatorOneQuadInternalVariant"
+        );
+        assert_eq!(
+            mirrored_internal_name("RotatorHalfInternalVariant"),
+            "RotatorHalfInternalVariant"
+        );
+
+        let mut mirrored = blueprint(
+            r#"[{"X":2,"Y":1,"R":1,"T":"RotatorOneQuadInternalVariant"},
+                {"X":3,"Y":1,"T":"RotatorOneQuadCCWInternalVariant"}]"#,
+        );
+        mirrored.mirror(Axis::X).unwrap();
+
+        assert_eq!(
+            mirrored[0].internal_name(),
+            "RotatorOneQuadCCWInternalVariant"
+        );
+        assert_eq!(mirrored[0].tile(), (-2, 1, 0));
+        assert_eq!(mirrored[0].quarter_turns(), 1);
+        assert_eq!(mirrored[1].internal_name(), "RotatorOneQuadInternalVariant");
+        assert_eq!(mirrored[1].tile(), (-3, 1, 0));
+        assert_eq!(mirrored[1].quarter_turns(), 2);
+
+        mirrored.mirror(Axis::X).unwrap();
+        assert_eq!(mirrored[0].internal_name(), "RotatorOneQuadInternalVariant");
+        assert_eq!(mirrored[0].tile(), (2, 1, 0));
+        assert_eq!(mirrored[0].quarter_turns(), 1);
+    }
+
+    #[test]
+    fn normalizing_moves_minimum_tile_to_origin() {
+        let mut normalized = blueprint(
+            r#"[{"X":-3,"Y":5,"L":2,"T":"BeltDefaultForwardInternalVariant"},
+                {"X":4,"Y":-2,"T":"CutterDefaultInternalVariant"}]"#,
+        );
+        normalized.normalize().unwrap();
+
+        // The cutter covers the tile behind it, so it sets the minimum y
+        assert_eq!(normalized[0].tile(), (0, 8, 2));
+        assert_eq!(normalized[1].tile(), (7, 1, 0));
+        assert_eq!(sorted_tiles(&normalized[1]), [(7, 0, 0), (7, 1, 0)]);
+
+        let min_x = normalized.iter().map(|entry| entry.bounds().0 .0).min();
+        let min_y = normalized.iter().map(|entry| entry.bounds().0 .1).min();
+        assert_eq!((min_x, min_y), (Some(0), Some(0)));
+    }
 }
@@ -0,0 +1,320 @@
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use serde::{Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+
+/// The settings attached to a building within a blueprint (the `C` field of an entry).
+///
+/// The settings are stored as base64 encoded binary data, where the layout depends on the kind of
+/// building. Settings which are not understood, either because the building is not recognized or
+/// because the data does not match the expected layout, are kept as [`BuildingConfig::Raw`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BuildingConfig {
+    /// The building has no attached settings
+    Empty,
+    /// The text shown by a label
+    Label { text: String },
+    /// The value emitted by a constant signal producer
+    ConstantSignal { signal: SignalValue },
+    /// The shape or fluid produced by a sandbox item producer
+    ItemProducer { item: SignalValue },
+    /// The color of the fluid produced by a sandbox fluid producer
+    FluidProducer { color: char },
+    /// The item allowed through a filter
+    Filter { item: SignalValue },
+    /// Whether a button is pressed
+    Button { pressed: bool },
+    /// Settings which could not be decoded
+    Raw {
+        #[serde(serialize_with = "serialize_base64")]
+        data: Vec<u8>,
+    },
+}
+
+/// A value carried by the wires of a blueprint
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum SignalValue {
+    Null,
+    Conflict,
+    Integer(i32),
+    Boolean(bool),
+    /// A shape, written as a shape code such as `CuCuCuCu`
+    Shape(String),
+    /// A fluid, written as its color code such as `r`
+    Fluid(char),
+}
+
+/// The kinds of buildings with settings that can be decoded
+#[derive(Copy, Clone)]
+enum ConfigKind {
+    Label,
+    ConstantSignal,
+    ItemProducer,
+    FluidProducer,
+    Filter,
+    Button,
+}
+
+impl ConfigKind {
+    fn for_building(internal_name: &str) -> Option<Self> {
+        const PREFIXES: [(&str, ConfigKind); 6] = [
+            ("Label", ConfigKind::Label),
+            ("ConstantSignal", ConfigKind::ConstantSignal),
+            ("SandboxItemProducer", ConfigKind::ItemProducer),
+            ("SandboxFluidProducer", ConfigKind::FluidProducer),
+            ("Filter", ConfigKind::Filter),
+            ("Button", ConfigKind::Button),
+        ];
+
+        PREFIXES
+            .into_iter()
+            .find(|(prefix, _)| internal_name.starts_with(prefix))
+            .map(|(_, kind)| kind)
+    }
+}
+
+impl BuildingConfig {
+    /// Decode the settings attached to a building with the given internal name.
+    pub fn decode(internal_name: &str, attached_data: &str) -> Self {
+        if attached_data.is_empty() {
+            return BuildingConfig::Empty;
+        }
+
+        let data = match BASE64_STANDARD.decode(attached_data) {
+            Ok(data) => data,
+            Err(_) => return BuildingConfig::raw(attached_data.as_bytes()),
+        };
+
+        ConfigKind::for_building(internal_name)
+            .and_then(|kind| Self::decode_kind(kind, &data))
+            .unwrap_or_else(|| BuildingConfig::raw(&data))
+    }
+
+    fn decode_kind(kind: ConfigKind, data: &[u8]) -> Option<Self> {
+        let mut reader = ByteReader { data };
+
+        let config = match kind {
+            ConfigKind::Label => BuildingConfig::Label {
+                text: reader.string()?,
+            },
+            ConfigKind::ConstantSignal => BuildingConfig::ConstantSignal {
+                signal: reader.signal()?,
+            },
+            ConfigKind::ItemProducer => BuildingConfig::ItemProducer {
+                item: reader.signal()?,
+            },
+            ConfigKind::FluidProducer => BuildingConfig::FluidProducer {
+                color: reader.color()?,
+            },
+            ConfigKind::Filter => BuildingConfig::Filter {
+                item: reader.signal()?,
+            },
+            ConfigKind::Button => BuildingConfig::Button {
+                pressed: reader.boolean()?,
+            },
+        };
+
+        // Trailing data means the layout was not what we expected
+        reader.data.is_empty().then_some(config)
+    }
+
+    /// Get the color code of the fluid named by the settings, if any.
+    pub fn fluid(&self) -> Option<char> {
+        match self {
+            BuildingConfig::FluidProducer { color } => Some(*color),
+            BuildingConfig::ConstantSignal {
+                signal: SignalValue::Fluid(color),
+            }
+            | BuildingConfig::ItemProducer {
+                item: SignalValue::Fluid(color),
+            }
+            | BuildingConfig::Filter {
+                item: SignalValue::Fluid(color),
+            } => Some(*color),
+            _ => None,
+        }
+    }
+
+    fn raw(data: &[u8]) -> Self {
+        BuildingConfig::Raw {
+            data: data.to_vec(),
+        }
+    }
+}
+
+impl Display for BuildingConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildingConfig::Empty => write!(f, "empty"),
+            BuildingConfig::Label { text } => write!(f, "label {:?}", text),
+            BuildingConfig::ConstantSignal { signal } => write!(f, "signal {}", signal),
+            BuildingConfig::ItemProducer { item } => write!(f, "produces {}", item),
+            BuildingConfig::FluidProducer { color } => write!(f, "produces fluid {}", color),
+            BuildingConfig::Filter { item } => write!(f, "filters {}", item),
+            BuildingConfig::Button { pressed: true } => write!(f, "pressed"),
+            BuildingConfig::Button { pressed: false } => write!(f, "not pressed"),
+            BuildingConfig::Raw { data } => write!(f, "raw {}", BASE64_STANDARD.encode(data)),
+        }
+    }
+}
+
+impl Display for SignalValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignalValue::Null => write!(f, "null"),
+            SignalValue::Conflict => write!(f, "conflict"),
+            SignalValue::Integer(value) => write!(f, "{}", value),
+            SignalValue::Boolean(value) => write!(f, "{}", value),
+            SignalValue::Shape(code) => write!(f, "shape {}", code),
+            SignalValue::Fluid(color) => write!(f, "fluid {}", color),
+        }
+    }
+}
+
+/// Reads the little endian values used within the attached settings of a building
+struct ByteReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, remaining) = self.data.split_first_chunk::<N>()?;
+        self.data = remaining;
+        Some(*bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|[byte]| byte)
+    }
+
+    fn boolean(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    /// A UTF-8 string prefixed by its length in bytes as an `i16`. A length of -1 marks an empty
+    /// string.
+    fn string(&mut self) -> Option<String> {
+        let len = i16::from_le_bytes(self.bytes()?);
+        if len == -1 {
+            return Some(String::new());
+        }
+
+        let len = usize::try_from(len).ok()?;
+        if self.data.len() < len {
+            return None;
+        }
+
+        let (text, remaining) = self.data.split_at(len);
+        self.data = remaining;
+        String::from_utf8(text.to_vec()).ok()
+    }
+
+    /// A fluid color, stored as the ASCII character of its color code
+    fn color(&mut self) -> Option<char> {
+        Some(self.u8()?)
+            .filter(u8::is_ascii_alphabetic)
+            .map(char::from)
+    }
+
+    /// A signal, stored as a type tag followed by the value
+    fn signal(&mut self) -> Option<SignalValue> {
+        Some(match self.u8()? {
+            0 => SignalValue::Null,
+            1 => SignalValue::Conflict,
+            2 => SignalValue::Integer(i32::from_le_bytes(self.bytes()?)),
+            3 => SignalValue::Boolean(false),
+            4 => SignalValue::Boolean(true),
+            5 => SignalValue::Shape(self.string()?),
+            6 => SignalValue::Fluid(self.color()?),
+            _ => return None,
+        })
+    }
+}
+
+fn serialize_base64<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&BASE64_STANDARD.encode(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_labels() {
+        assert_eq!(
+            BuildingConfig::decode("LabelDefaultInternalVariant", "BQBIRUxMTw=="),
+            BuildingConfig::Label {
+                text: "HELLO".to_string()
+            }
+        );
+        assert_eq!(
+            BuildingConfig::decode("LabelDefaultInternalVariant", "//8="),
+            BuildingConfig::Label {
+                text: String::new()
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_signals() {
+        let signal =
+            |data| match BuildingConfig::decode("ConstantSignalDefaultInternalVariant", data) {
+                BuildingConfig::ConstantSignal { signal } => signal,
+                config => panic!("expected a constant signal, got {:?}", config),
+            };
+
+        assert_eq!(signal("AA=="), SignalValue::Null);
+        assert_eq!(signal("AgEAAAA="), SignalValue::Integer(1));
+        assert_eq!(signal("Avb///8="), SignalValue::Integer(-10));
+        assert_eq!(signal("BA=="), SignalValue::Boolean(true));
+        assert_eq!(
+            signal("BQgAQ3VDdUN1Q3U="),
+            SignalValue::Shape("CuCuCuCu".to_string())
+        );
+        assert_eq!(signal("BnI="), SignalValue::Fluid('r'));
+    }
+
+    #[test]
+    fn decodes_fluids() {
+        let producer = BuildingConfig::decode("SandboxFluidProducerDefaultInternalVariant", "cg==");
+        assert_eq!(producer, BuildingConfig::FluidProducer { color: 'r' });
+        assert_eq!(producer.fluid(), Some('r'));
+
+        let item = BuildingConfig::decode("SandboxItemProducerDefaultInternalVariant", "BmI=");
+        assert_eq!(item.fluid(), Some('b'));
+
+        let shape = BuildingConfig::decode(
+            "SandboxItemProducerDefaultInternalVariant",
+            "BQgAUnVSdVJ1UnU=",
+        );
+        assert_eq!(shape.fluid(), None);
+    }
+
+    #[test]
+    fn keeps_unknown_settings_raw() {
+        assert_eq!(
+            BuildingConfig::decode("BeltDefaultForwardInternalVariant", ""),
+            BuildingConfig::Empty
+        );
+        assert_eq!(
+            BuildingConfig::decode("ButtonDefaultInternalVariant", "BAA="),
+            BuildingConfig::Raw { data: vec![4, 0] }
+        );
+        assert_eq!(
+            BuildingConfig::decode("BeltDefaultForwardInternalVariant", "BA=="),
+            BuildingConfig::Raw { data: vec![4] }
+        );
+        assert_eq!(
+            BuildingConfig::decode("LabelDefaultInternalVariant", "not base64"),
+            BuildingConfig::Raw {
+                data: b"not base64".to_vec()
+            }
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use crate::blueprint::DecodeError;
+use crate::render::{GlError, ShaderError};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Any error which may occur while reading, rendering or writing a blueprint
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to read the blueprint or write the output
+    Io(io::Error),
+    /// The blueprint could not be decoded
+    Decode(DecodeError),
+    /// A transformation could not be applied to a blueprint
+    Transform(String),
+    /// The model directory does not exist or is not a directory
+    ModelDirectory(PathBuf),
+    /// A model mapping file could not be parsed
+    Mapping { path: PathBuf, message: String },
+    /// A model file exists, but could not be loaded
+    ModelLoad {
+        path: PathBuf,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A graphics context could not be created
+    Context(String),
+    /// A shader failed to compile or link
+    Shader(ShaderError),
+    /// OpenGL reported errors while rendering
+    Gl(Vec<GlError>),
+    /// The framebuffer being rendered to is incomplete
+    IncompleteFramebuffer(u32),
+    /// The rendered image could not be encoded
+    ImageEncode(image::ImageError),
+    /// A render option was given an invalid value
+    InvalidOption(String),
+    /// Some of the jobs within a batch failed to render
+    BatchFailed { failed: usize, total: usize },
+    /// A blueprint failed validation, either from errors or from warnings in strict mode
+    ValidationFailed { errors: usize, warnings: usize },
+    /// The render server could not listen on the requested address
+    Bind {
+        address: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "IO error: {}", err),
+            Error::Decode(err) => write!(f, "Failed to decode blueprint: {}", err),
+            Error::Transform(msg) => write!(f, "Failed to transform blueprint: {}", msg),
+            Error::ModelDirectory(path) => write!(
+                f,
+                "Expected model path {} to be a directory",
+                path.display()
+            ),
+            Error::Mapping { path, message } => {
+                write!(f, "Invalid model mapping {}: {}", path.display(), message)
+            }
+            Error::ModelLoad { path, source } => {
+                write!(f, "Failed to load model {}: {}", path.display(), source)
+            }
+            Error::Context(msg) => write!(f, "Failed to create graphics context: {}", msg),
+            Error::Shader(err) => write!(f, "{}", err),
+            Error::Gl(errors) => {
+                write!(f, "OpenGL error queue contained errors:")?;
+                for error in errors {
+                    write!(f, " {}", error)?;
+                }
+                Ok(())
+            }
+            Error::IncompleteFramebuffer(status) => {
+                write!(f, "Framebuffer is incomplete (status: {:#x})", status)
+            }
+            Error::ImageEncode(err) => write!(f, "Failed to encode image: {}", err),
+            Error::InvalidOption(msg) => write!(f, "Invalid render option: {}", msg),
+            Error::BatchFailed { failed, total } => {
+                write!(f, "{} of {} jobs failed", failed, total)
+            }
+            Error::ValidationFailed { errors, warnings } => write!(
+                f,
+                "Blueprint failed validation with {} errors and {} warnings",
+                errors, warnings
+            ),
+            Error::Bind { address, source } => {
+                write!(f, "Failed to listen on {}: {}", address, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Decode(err) => Some(err),
+            Error::ModelLoad { source, .. } => Some(source.as_ref()),
+            Error::Shader(err) => Some(err),
+            Error::ImageEncode(err) => Some(err),
+            Error::Bind { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        Error::Decode(err)
+    }
+}
+
+impl From<ShaderError> for Error {
+    fn from(err: ShaderError) -> Self {
+        Error::Shader(err)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Self {
+        Error::ImageEncode(err)
+    }
+}
@@ -0,0 +1,247 @@
+/// The tiles and layers covered by a building, given in the space of the building model before it
+/// is rotated. The anchor tile of the building (its position within the blueprint) is at `0, 0, 0`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Footprint {
+    /// The number of tiles along the x axis of the model
+    pub width: i32,
+    /// The number of tiles along the z axis of the model
+    pub depth: i32,
+    /// The number of layers covered
+    pub height: i32,
+    /// The corner of the footprint nearest negative infinity, as an offset from the anchor tile
+    /// along the x axis, z axis and layers of the model
+    pub origin: [i32; 3],
+}
+
+impl Default for Footprint {
+    fn default() -> Self {
+        Footprint::new(1, 1, 1)
+    }
+}
+
+/// Buildings covering more than their anchor tile, matched by the start of their internal name.
+/// The sizes follow the bounds of the models used to draw each building.
+const FOOTPRINTS: [(&str, Footprint); 10] = [
+    ("MixerDefault", Footprint::new(3, 2, 1)),
+    ("PainterDefault", Footprint::new(1, 2, 1)),
+    ("CutterDefault", Footprint::new(1, 2, 1)),
+    ("StackerDefault", Footprint::new(1, 1, 2)),
+    ("FluidStorage", Footprint::new(2, 2, 1).offset([-1, 0, 0])),
+    ("Storage", Footprint::new(2, 1, 2)),
+    ("Lift1Up", Footprint::new(1, 1, 2)),
+    ("Lift1Down", Footprint::new(1, 1, 2).offset([0, 0, -1])),
+    ("PipeUp", Footprint::new(1, 1, 2)),
+    ("PipeDown", Footprint::new(1, 1, 2).offset([0, 0, -1])),
+];
+
+impl Footprint {
+    const fn new(width: i32, depth: i32, height: i32) -> Self {
+        Footprint {
+            width,
+            depth,
+            height,
+            origin: [0, 0, 0],
+        }
+    }
+
+    const fn offset(self, origin: [i32; 3]) -> Self {
+        Footprint { origin, ..self }
+    }
+
+    /// Get the footprint of a building from its internal name. Buildings which are not known to
+    /// be larger cover a single tile.
+    pub fn for_building(internal_name: &str) -> Self {
+        FOOTPRINTS
+            .into_iter()
+            .find(|(prefix, _)| internal_name.starts_with(prefix))
+            .map(|(_, footprint)| footprint)
+            .unwrap_or_default()
+    }
+
+    /// Get the offsets of every tile covered by the footprint once the building is turned by a
+    /// number of quarter turns, as (x, y, layer) offsets from the anchor tile within the blueprint.
+    pub fn tiles(&self, quarter_turns: i32) -> Vec<(i32, i32, i32)> {
+        let [x0, z0, layer0] = self.origin;
+        let mut tiles = Vec::with_capacity((self.width * self.depth * self.height) as usize);
+
+        for layer in layer0..layer0 + self.height {
+            for x in x0..x0 + self.width {
+                for z in z0..z0 + self.depth {
+                    let (x, y) = rotate_offset(x, z, quarter_turns);
+                    tiles.push((x, y, layer));
+                }
+            }
+        }
+        tiles
+    }
+
+    /// Get the smallest and largest (x, y, layer) offsets covered by the footprint once the
+    /// building is turned by a number of quarter turns.
+    pub fn bounds(&self, quarter_turns: i32) -> ((i32, i32, i32), (i32, i32, i32)) {
+        let [x0, z0, layer0] = self.origin;
+        let (ax, ay) = rotate_offset(x0, z0, quarter_turns);
+        let (bx, by) = rotate_offset(x0 + self.width - 1, z0 + self.depth - 1, quarter_turns);
+
+        (
+            (ax.min(bx), ay.min(by), layer0),
+            (ax.max(bx), ay.max(by), layer0 + self.height - 1),
+        )
+    }
+}
+
+/// Turn a tile offset given along the x and z axes of a model into an offset within the
+/// blueprint. This matches the transform used to place building models, where the z axis of a
+/// model is flipped to face the -y axis of the blueprint when not rotated.
+fn rotate_offset(x: i32, z: i32, quarter_turns: i32) -> (i32, i32) {
+    match quarter_turns.rem_euclid(4) {
+        0 => (x, -z),
+        1 => (z, x),
+        2 => (-x, z),
+        _ => (-z, -x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_tiles(footprint: Footprint, quarter_turns: i32) -> Vec<(i32, i32, i32)> {
+        let mut tiles = footprint.tiles(quarter_turns);
+        tiles.sort();
+        tiles
+    }
+
+    #[test]
+    fn footprint_sizes() {
+        let size = |name: &str| {
+            let footprint = Footprint::for_building(name);
+            (footprint.width, footprint.depth, footprint.height)
+        };
+
+        assert_eq!(size("MixerDefaultInternalVariant"), (3, 2, 1));
+        assert_eq!(size("PainterDefaultInternalVariant"), (1, 2, 1));
+        assert_eq!(size("CutterDefaultInternalVariant"), (1, 2, 1));
+        assert_eq!(size("StackerDefaultInternalVariant"), (1, 1, 2));
+        assert_eq!(size("StorageDefaultInternalVariant"), (2, 1, 2));
+        assert_eq!(size("FluidStorageDefaultInternalVariant"), (2, 2, 1));
+        assert_eq!(size("BeltDefaultForwardInternalVariant"), (1, 1, 1));
+        assert_eq!(size("CutterHalfInternalVariant"), (1, 1, 1));
+
+        assert_eq!(
+            Footprint::for_building("FluidStorageDefaultInternalVariant").origin,
+            [-1, 0, 0]
+        );
+        assert_eq!(
+            Footprint::for_building("Lift1DownForwardInternalVariant").origin,
+            [0, 0, -1]
+        );
+    }
+
+    #[test]
+    fn rotate_offset_turns_clockwise() {
+        // The model z axis faces -y before the building is turned
+        assert_eq!(rotate_offset(0, 0, 0), (0, 0));
+        let turns: Vec<(i32, i32)> = (0..4).map(|turns| rotate_offset(2, 1, turns)).collect();
+        assert_eq!(turns, [(2, -1), (1, 2), (-2, 1), (-1, -2)]);
+
+        // Each quarter turn moves an offset from (x, y) to (-y, x)
+        for (index, (x, y)) in turns.iter().enumerate() {
+            assert_eq!(turns[(index + 1) % 4], (-y, *x));
+        }
+
+        assert_eq!(rotate_offset(2, 1, 4), rotate_offset(2, 1, 0));
+        assert_eq!(rotate_offset(2, 1, -1), rotate_offset(2, 1, 3));
+    }
+
+    #[test]
+    fn mixer_tiles_for_each_rotation() {
+        let mixer = Footprint::for_building("MixerDefaultInternalVariant");
+
+        let expected = [
+            (
+                vec![
+                    (0, -1, 0),
+                    (0, 0, 0),
+                    (1, -1, 0),
+                    (1, 0, 0),
+                    (2, -1, 0),
+                    (2, 0, 0),
+                ],
+                ((0, -1, 0), (2, 0, 0)),
+            ),
+            (
+                vec![
+                    (0, 0, 0),
+                    (0, 1, 0),
+                    (0, 2, 0),
+                    (1, 0, 0),
+                    (1, 1, 0),
+                    (1, 2, 0),
+                ],
+                ((0, 0, 0), (1, 2, 0)),
+            ),
+            (
+                vec![
+                    (-2, 0, 0),
+                    (-2, 1, 0),
+                    (-1, 0, 0),
+                    (-1, 1, 0),
+                    (0, 0, 0),
+                    (0, 1, 0),
+                ],
+                ((-2, 0, 0), (0, 1, 0)),
+            ),
+            (
+                vec![
+                    (-1, -2, 0),
+                    (-1, -1, 0),
+                    (-1, 0, 0),
+                    (0, -2, 0),
+                    (0, -1, 0),
+                    (0, 0, 0),
+                ],
+                ((-1, -2, 0), (0, 0, 0)),
+            ),
+        ];
+        for (quarter_turns, (tiles, bounds)) in expected.into_iter().enumerate() {
+            let quarter_turns = quarter_turns as i32;
+            assert_eq!(sorted_tiles(mixer, quarter_turns), tiles);
+            assert_eq!(mixer.bounds(quarter_turns), bounds);
+        }
+    }
+
+    #[test]
+    fn bounds_enclose_tiles() {
+        for (name, footprint) in FOOTPRINTS {
+            for quarter_turns in 0..4 {
+                let tiles = footprint.tiles(quarter_turns);
+                let min = |axis: fn(&(i32, i32, i32)) -> i32| tiles.iter().map(axis).min().unwrap();
+                let max = |axis: fn(&(i32, i32, i32)) -> i32| tiles.iter().map(axis).max().unwrap();
+
+                let expected = (
+                    (min(|t| t.0), min(|t| t.1), min(|t| t.2)),
+                    (max(|t| t.0), max(|t| t.1), max(|t| t.2)),
+                );
+                assert_eq!(
+                    footprint.bounds(quarter_turns),
+                    expected,
+                    "{} turned {} times",
+                    name,
+                    quarter_turns
+                );
+                assert_eq!(
+                    tiles.len() as i32,
+                    footprint.width * footprint.depth * footprint.height
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lift_down_covers_layer_below() {
+        let lift = Footprint::for_building("Lift1DownForwardInternalVariant");
+        for quarter_turns in 0..4 {
+            assert_eq!(sorted_tiles(lift, quarter_turns), [(0, 0, -1), (0, 0, 0)]);
+        }
+    }
+}
@@ -3,16 +3,51 @@ layout (location = 0) out vec4 FragColor;
 
 in vec3 Position;
 in vec3 Normal;
+in vec4 LightSpacePosition;
 
 uniform vec3 camera;
 uniform vec3 lightDirection;
 uniform vec3 materialColor;
+uniform float materialRoughness;
+uniform float materialEmission;
+uniform float materialOpacity;
 
+uniform bool shadowsEnabled;
+uniform sampler2DShadow shadowMap;
+uniform int shadowFilterRadius;
+uniform float shadowBias;
+uniform float shadowStrength;
+
+uniform bool ambientOcclusionEnabled;
+uniform sampler2D ambientOcclusion;
+uniform float ambientOcclusionStrength;
+
+// The fraction of light from the light source which is blocked before reaching this fragment
+float shadow() {
+    vec3 position = LightSpacePosition.xyz / LightSpacePosition.w * 0.5 + 0.5;
+    if (position.z > 1.0) {
+        return 0.0;
+    }
+
+    // Percentage closer filtering averages the comparison over the surrounding texels
+    vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0));
+    float lit = 0.0;
+    for (int x = -shadowFilterRadius; x <= shadowFilterRadius; x++) {
+        for (int y = -shadowFilterRadius; y <= shadowFilterRadius; y++) {
+            vec2 offset = vec2(x, y) * texelSize;
+            lit += texture(shadowMap, vec3(position.xy + offset, position.z - shadowBias));
+        }
+    }
+
+    float samples = float((2 * shadowFilterRadius + 1) * (2 * shadowFilterRadius + 1));
+    return 1.0 - lit / samples;
+}
 
 void main() {
     // Material properties
 //    vec3 materialColor = vec3(0.18823, 0.51372, 0.86274);
-    float shininess = 0.5;
+    // Fully rough materials keep the soft shading of the blueprint style
+    float shininess = mix(16.0, 0.5, materialRoughness);
     float ambientLight = 0.1;
 
     vec3 viewDirection = normalize(camera - Position);
@@ -23,7 +58,24 @@ void main() {
 
     vec3 ambient = materialColor * ambientLight;
 
-    vec3 totalLight = specular + ambient;
+    // Smooth materials also reflect a white highlight
+    float highlight = pow(max(dot(Normal, halfwayDir), 0.0), 64.0) * (1.0 - materialRoughness);
+
+    vec3 emitted = materialColor * materialEmission;
+
+    vec3 totalLight = specular + ambient + vec3(highlight) + emitted;
+
+    // Shadows only block the direct light
+    if (shadowsEnabled) {
+        totalLight -= (specular + vec3(highlight)) * shadowStrength * shadow();
+    }
+
+    // Occlusion darkens the light reflected by a surface, but not the light it emits. Translucent
+    // surfaces are not part of the occlusion buffer, so they are left as is.
+    if (ambientOcclusionEnabled && materialOpacity >= 1.0) {
+        float occlusion = texelFetch(ambientOcclusion, ivec2(gl_FragCoord.xy), 0).r;
+        totalLight -= (totalLight - emitted) * min(ambientOcclusionStrength * occlusion, 1.0);
+    }
 
 
 //    vec3 normalColor = normalize(Normal / 2.5 + 0.25);
@@ -32,5 +84,6 @@ void main() {
 
     // Gamma correction
     float gamma = 2.2;
-    FragColor = vec4(pow(totalLight, vec3(1.0 / gamma)), 1.0);
+    // Output premultiplied alpha
+    FragColor = vec4(pow(totalLight, vec3(1.0 / gamma)) * materialOpacity, materialOpacity);
 }
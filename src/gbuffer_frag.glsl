@@ -0,0 +1,22 @@
+#version 330 core
+layout (location = 0) out vec4 GBuffer;
+
+in vec3 ViewPosition;
+in vec3 ViewNormal;
+
+uniform mat4 projection;
+
+void main() {
+    vec3 normal = normalize(ViewNormal);
+
+    // Both sides of each surface are drawn, so turn the normal to face the camera
+    bool orthographic = projection[2][3] == 0.0;
+    vec3 toCamera = orthographic ? vec3(0.0, 0.0, 1.0) : -ViewPosition;
+    if (dot(normal, toCamera) < 0.0) {
+        normal = -normal;
+    }
+
+    // The distance in front of the camera is kept in place of the depth buffer, as it is far more
+    // precise for distant surfaces
+    GBuffer = vec4(normal, -ViewPosition.z);
+}
@@ -0,0 +1,18 @@
+#version 330 core
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 normal;
+
+out vec3 ViewPosition;
+out vec3 ViewNormal;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+void main() {
+    vec4 viewPosition = view * model * vec4(position, 1.0);
+    ViewPosition = viewPosition.xyz;
+    // Models are only rotated, moved and mirrored, so normals can use the same transform
+    ViewNormal = mat3(view * model) * normal;
+    gl_Position = projection * viewPosition;
+}
@@ -0,0 +1,25 @@
+//! Render blueprints from the game Shapez 2 into images.
+//!
+//! A blueprint is decoded with [`Blueprint`], the models for each building are resolved through a
+//! [`ModelLoader`], and finally a [`Renderer`] draws the result using a set of [`RenderOptions`].
+
+pub mod batch;
+pub mod blueprint;
+pub mod building_config;
+pub mod error;
+pub mod footprint;
+pub mod mapping;
+pub mod output;
+pub mod render;
+pub mod server;
+pub mod stats;
+pub mod tweaks;
+pub mod validate;
+pub mod worker;
+
+pub use blueprint::{Axis, Blueprint, BlueprintEntry};
+pub use building_config::{BuildingConfig, SignalValue};
+pub use error::{Error, Result};
+pub use mapping::ModelMapping;
+pub use render::{GlRenderer, RenderOptions, RenderOverrides, Renderer, SoftwareRenderer};
+pub use tweaks::ModelLoader;
@@ -1,30 +1,49 @@
-use crate::blueprint::Blueprint;
-use crate::tweaks::ModelLoader;
 use clap::builder::PossibleValue;
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use clap_verbosity_flag::{InfoLevel, LogLevel, Verbosity};
 use image::imageops::FilterType;
+use image::{ImageFormat, RgbaImage};
 use lazy_static::lazy_static;
 use log::{error, info, set_logger, set_max_level, Log, Metadata, Record};
-use std::io::{stderr, Write};
-use std::path::PathBuf;
+use nalgebra_glm::Vec4;
+use shapez2_blueprint_renderer::batch::run_batch;
+use shapez2_blueprint_renderer::output::{encode_image, save_image};
+use shapez2_blueprint_renderer::render::{
+    parse_color, parse_point, CameraOptions, CameraOverrides, Projection, RenderStyle,
+    ShadowQuality, ViewPreset, DEFAULT_FOV, MAX_RENDER_SIZE,
+};
+use shapez2_blueprint_renderer::server::RenderServer;
+use shapez2_blueprint_renderer::stats::BlueprintStats;
+use shapez2_blueprint_renderer::validate::validate;
+use shapez2_blueprint_renderer::worker::Worker;
+use shapez2_blueprint_renderer::{
+    Axis, Blueprint, BuildingConfig, Error, GlRenderer, ModelLoader, ModelMapping, RenderOptions,
+    Renderer, Result, SoftwareRenderer,
+};
+use std::fs;
+use std::fs::File;
+use std::io::{stderr, stdin, stdout, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::time::Instant;
 
-mod blueprint;
-mod render;
-mod tweaks;
-
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// The file which to read the blueprint from. If an input file is not provided, the blueprint
     /// will instead be read from stdin.
     input_file: Option<PathBuf>,
     /// The directory holding the .obj files representing the various buildings and features within
     /// the game.
-    #[arg(short, long, default_value = "./models")]
+    #[arg(short, long, global = true, default_value = "./models")]
     model_dir: PathBuf,
+    /// A TOML or JSON file describing the models used to draw each building. Entries in this file
+    /// replace those of the built-in mapping and any mapping.toml or mapping.json file within the
+    /// model directory. Every model file named by the mapping must exist within the model directory.
+    #[arg(long, global = true)]
+    mapping: Option<PathBuf>,
     /// The path that the output image will be written to. The image type is detected from the path
     /// extension. If an output file is not provided, the image will instead be written to stdout as
     /// a PNG.
@@ -33,10 +52,10 @@ struct Args {
     #[clap(flatten)]
     verbose: Verbosity<InfoLevel>,
     /// The width of the output image
-    #[arg(long, default_value = "1980")]
+    #[arg(long, global = true, default_value = "1980")]
     width: u32,
     /// The height of the output image
-    #[arg(long, default_value = "1080")]
+    #[arg(long, global = true, default_value = "1080")]
     height: u32,
     /// This argument triggers SSAA on the rendered image. This is provided to allow for
     /// anti-aliasing on systems which do not normally support MSAA. Values over 16 will not
@@ -45,12 +64,243 @@ struct Args {
     /// Note: This is applied by increasing the render size and resampling the output. As such,
     /// this is NOT hardware accelerated and is performed on top of any MSAA capabilities the
     /// system has.
-    #[arg(long, default_value = "1")]
+    #[arg(long, global = true, default_value = "1")]
     ssaa: u32,
     /// The sampler used when resizing a super sampled image to the intended size. This will effect
     /// the final image quality when resizing is required.
-    #[arg(long, value_enum, default_value = "linear")]
+    #[arg(long, global = true, value_enum, default_value = "linear")]
     ssaa_sampler: ImageFilter,
+    /// The rendering backend used to draw the blueprint
+    #[arg(long, global = true, value_enum, default_value = "gl")]
+    backend: Backend,
+    /// Render without a window by creating a surfaceless EGL context and drawing into an offscreen
+    /// framebuffer. This removes the need for a display server (such as xvfb) and allows for
+    /// renders larger than the screen size.
+    #[arg(long, global = true)]
+    headless: bool,
+    /// The background color of the image, written as #rrggbb, #rrggbbaa or transparent. When not
+    /// given, the background is a dark grey. Note that the ground plane covers the background in
+    /// most views, so it usually needs to be disabled with --no-ground as well.
+    #[arg(long, global = true, value_parser = parse_color)]
+    background: Option<Vec4>,
+    /// Do not draw the ground plane beneath the blueprint
+    #[arg(long, global = true)]
+    no_ground: bool,
+    /// How buildings are drawn
+    #[arg(long, global = true, value_enum, default_value = "blueprint")]
+    style: RenderStyle,
+    /// Cast shadows from the light onto the ground and other buildings
+    #[arg(long, global = true)]
+    shadows: bool,
+    /// The resolution and filtering of shadows when enabled with --shadows
+    #[arg(long, global = true, value_enum, default_value = "medium")]
+    shadow_quality: ShadowQuality,
+    /// Darken the creases and corners between buildings with screen space ambient occlusion. Only
+    /// supported by the gl backend.
+    #[arg(long, global = true)]
+    ssao: bool,
+    /// The fraction of light blocked within fully occluded creases when enabled with --ssao
+    #[arg(long, global = true, default_value = "1")]
+    ssao_strength: f32,
+    /// The distance in tiles around each point which is searched for occluding surfaces when
+    /// enabled with --ssao
+    #[arg(long, global = true, default_value = "0.5")]
+    ssao_radius: f32,
+    #[command(flatten)]
+    camera: CameraArgs,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(next_help_heading = "Camera")]
+struct CameraArgs {
+    /// A preset camera view. The other camera options are applied on top of the preset.
+    #[arg(long, global = true, value_enum, default_value = "default")]
+    view: ViewPreset,
+    /// The rotation of the camera around the vertical axis in degrees. By default, the camera is
+    /// turned to face the longest side of the blueprint.
+    #[arg(long, global = true, allow_negative_numbers = true)]
+    yaw: Option<f32>,
+    /// The angle of the camera above the horizon in degrees. A pitch of 90 looks straight down. By
+    /// default, the pitch of the view is used, which is 45 for the default view.
+    #[arg(long, global = true, allow_negative_numbers = true)]
+    pitch: Option<f32>,
+    /// The vertical field of view of the camera in degrees
+    #[arg(long, global = true, default_value_t = DEFAULT_FOV)]
+    fov: f32,
+    /// The fraction of extra space to leave around the blueprint when fitting it to the image.
+    /// Negative values zoom in past the edges of the blueprint.
+    #[arg(
+        long,
+        global = true,
+        allow_negative_numbers = true,
+        default_value = "0.05"
+    )]
+    padding: f32,
+    /// The distance between the camera and the point it looks at. By default, the distance is
+    /// chosen so the entire blueprint is visible.
+    #[arg(long, global = true)]
+    distance: Option<f32>,
+    /// The point the camera looks at, given as x,y,z. By default, the default view looks at the
+    /// origin while other views look at the center of the blueprint.
+    #[arg(long, global = true, allow_negative_numbers = true, value_parser = parse_point)]
+    target: Option<[f32; 3]>,
+    /// The projection used by the camera. By default, the default view uses a perspective
+    /// projection while other views are orthographic.
+    #[arg(long, global = true, value_enum)]
+    projection: Option<Projection>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render many blueprints with a single renderer. Render jobs are read from stdin as newline
+    /// delimited JSON and the result of each job is written to stdout as a single line of JSON.
+    ///
+    /// Each job is an object holding a `blueprint` string and optionally an `id`, `width`,
+    /// `height`, `ssaa`, `camera` object, `background`, `ground_plane`, `style`, `shadows`,
+    /// `shadow_quality`, `ssao`, `ssao_strength`, `ssao_radius` and `output` path. Any options
+    /// not given by a job default to the values passed on the command line. When no output path is
+    /// given, the result holds the image as a base64 encoded PNG.
+    Worker,
+    /// Render every job listed in a manifest file using a single renderer. Failed jobs do not stop
+    /// the batch, and a summary of every job is printed once the batch completes.
+    Batch {
+        /// The manifest listing the jobs to render. Files ending in `.csv` are read as CSV with a
+        /// header row, while any other file is read as newline delimited JSON. Each job holds an
+        /// `output` path, either a `blueprint` string or a `blueprint_file`, and optionally a
+        /// `width`, `height`, `ssaa`, `background`, `ground_plane`, `style`, `shadows`,
+        /// `shadow_quality`, `ssao`, `ssao_strength`, `ssao_radius` and camera settings. Relative
+        /// paths are resolved relative to the manifest.
+        manifest: PathBuf,
+        /// Write the summary as JSON to this path in addition to printing it
+        #[arg(long)]
+        summary: Option<PathBuf>,
+    },
+    /// Encode a blueprint from its JSON form into a blueprint string, which is written to stdout.
+    /// This is the JSON document found within a blueprint string, such as
+    /// `{"V": 1122, "BP": {"$type": "Building", "Entries": [...]}}`.
+    Encode {
+        /// The JSON file to read. If not provided, the JSON is read from stdin.
+        input: Option<PathBuf>,
+    },
+    /// Decode a blueprint string and print its contents. This is useful for inspecting blueprints
+    /// which fail to render.
+    Decode {
+        /// The file to read the blueprint string from. If not provided, the blueprint is read from
+        /// stdin.
+        input: Option<PathBuf>,
+        /// How to print the decoded blueprint
+        #[arg(long, value_enum, default_value = "json")]
+        format: DecodeFormat,
+        /// When decoding fails, report which stage of decoding (prefix, base64, gzip or JSON)
+        /// failed and the byte offset where it failed.
+        #[arg(long)]
+        diagnose: bool,
+    },
+    /// Transform a blueprint and write the result to stdout as a blueprint string. The
+    /// transformations are applied in the order mirror, rotate, translate and then normalize.
+    /// Island blueprints can not be transformed.
+    Transform {
+        /// The file to read the blueprint string from. If not provided, the blueprint is read from
+        /// stdin.
+        input: Option<PathBuf>,
+        /// Mirror the blueprint by negating its x or y coordinates. Buildings with left and right
+        /// variants are swapped with the opposite variant.
+        #[arg(long, value_enum)]
+        mirror: Option<MirrorAxis>,
+        /// Rotate the blueprint clockwise by this many quarter turns about the origin
+        #[arg(long, allow_negative_numbers = true, default_value = "0")]
+        rotate: i32,
+        /// Move the blueprint by an offset in tiles, given as x,y or x,y,layer
+        #[arg(long, allow_negative_numbers = true, value_parser = parse_offset)]
+        translate: Option<[i32; 3]>,
+        /// Move the blueprint so its minimum x and y are at the origin
+        #[arg(long)]
+        normalize: bool,
+    },
+    /// Print a summary of a blueprint, including the number of each building, the area covered on
+    /// each layer, how many buildings have models and an estimate of the blueprint cost.
+    Stats {
+        /// The file to read the blueprint string from. If not provided, the blueprint is read from
+        /// stdin.
+        input: Option<PathBuf>,
+        /// How to print the summary
+        #[arg(long, value_enum, default_value = "text")]
+        format: ReportFormat,
+    },
+    /// Check a blueprint for overlapping buildings, invalid rotations and layers, unknown buildings
+    /// and unsupported versions. Every problem found is printed along with the index of each entry
+    /// involved, and the command fails when any errors are found.
+    Validate {
+        /// The file to read the blueprint string from. If not provided, the blueprint is read from
+        /// stdin.
+        input: Option<PathBuf>,
+        /// How to print the problems found
+        #[arg(long, value_enum, default_value = "text")]
+        format: ReportFormat,
+        /// Fail when any warnings are found as well as errors
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Start an HTTP server which renders blueprints. All renders are performed on a single
+    /// thread, with requests waiting in a queue until the renderer is available.
+    ///
+    /// Blueprints are rendered by sending the blueprint string as the body of a `POST /render`
+    /// request. The query parameters `width`, `height`, `ssaa`, `format` (such as `png` or
+    /// `jpg`), `background`, `ground`, `style`, `shadows`, `shadow_quality`, `ssao`,
+    /// `ssao_strength`, `ssao_radius`, `view`, `yaw`, `pitch`, `fov`, `padding`, `distance`,
+    /// `target` and `projection` override the options passed on the command line. The server also
+    /// provides `GET /health` and `GET /stats` endpoints.
+    Serve {
+        /// The address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address: String,
+        /// The largest width or height of a render, including SSAA, which may be requested.
+        /// Larger requests are rejected.
+        #[arg(long, default_value_t = 8192)]
+        max_size: u32,
+    },
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum Backend {
+    /// Render using OpenGL
+    Gl,
+    /// Render using a software rasterizer on the CPU. This is slower, but does not require any
+    /// graphics drivers.
+    Cpu,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum DecodeFormat {
+    /// The JSON document held within the blueprint string, pretty printed
+    Json,
+    /// A table listing the position, rotation, name and settings of each building
+    Table,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum ReportFormat {
+    /// A human readable report
+    Text,
+    /// The report as pretty printed JSON
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum MirrorAxis {
+    /// Negate the x coordinate, flipping the blueprint left to right
+    X,
+    /// Negate the y coordinate, flipping the blueprint front to back
+    Y,
+}
+
+impl From<MirrorAxis> for Axis {
+    fn from(axis: MirrorAxis) -> Self {
+        match axis {
+            MirrorAxis::X => Axis::X,
+            MirrorAxis::Y => Axis::Y,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -103,43 +353,389 @@ fn main() {
     set_logger(Box::leak(logger)).expect("no other logger has been registered");
     set_max_level(ARGS.verbose.log_level_filter());
 
-    if !ARGS.model_dir.is_dir() {
-        error!(
-            "expected model path {} to be a directory.",
-            ARGS.model_dir.display()
-        );
-        error!("You can use '--model-dir <path>' to specify a different path");
+    let result = match ARGS.command {
+        Some(Command::Worker) => run_worker(),
+        Some(Command::Batch {
+            ref manifest,
+            ref summary,
+        }) => run_batch_command(manifest, summary.as_deref()),
+        Some(Command::Encode { ref input }) => run_encode(input.as_deref()),
+        Some(Command::Decode {
+            ref input,
+            format,
+            diagnose,
+        }) => run_decode(input.as_deref(), format, diagnose),
+        Some(Command::Transform {
+            ref input,
+            mirror,
+            rotate,
+            translate,
+            normalize,
+        }) => run_transform(input.as_deref(), mirror, rotate, translate, normalize),
+        Some(Command::Stats { ref input, format }) => run_stats(input.as_deref(), format),
+        Some(Command::Validate {
+            ref input,
+            format,
+            strict,
+        }) => run_validate(input.as_deref(), format, strict),
+        Some(Command::Serve {
+            ref address,
+            max_size,
+        }) => run_server(address, max_size),
+        None => run(program_start_time),
+    };
+
+    if let Err(err) = result {
+        error!("{}", err);
+
+        if let Error::ModelDirectory(_) = err {
+            error!("You can use '--model-dir <path>' to specify a different path");
+        }
+
         exit(1);
     }
+}
+
+fn run(program_start_time: Instant) -> Result<()> {
+    let options = render_options()?;
+    let mut loader = create_model_loader()?;
 
     let parse_start_time = Instant::now();
     let blueprint = match &ARGS.input_file {
-        Some(file) => Blueprint::read_from_file(file),
-        None => Blueprint::read_from_stdin(),
+        Some(file) => Blueprint::read_from_file(file)?,
+        None => Blueprint::read_from_stdin()?,
     };
     info!("Blueprint parse duration: {:?}", parse_start_time.elapsed());
 
-    let mut loader = ModelLoader::new(&ARGS.model_dir);
-
     // Preloading the models just makes it so that the model load time is not added to the outputted total render time
     let model_preload_start_time = Instant::now();
     for entry in &*blueprint {
-        loader.load_model(entry.internal_name());
+        loader.load_model(entry.internal_name())?;
     }
     info!(
         "Preloaded model .obj files used by blueprint in {:?}",
         model_preload_start_time.elapsed()
     );
 
-    if let Err(err) = render::perform_render(&blueprint, &mut loader) {
-        error!("Encountered rendering error: {}", err);
-        exit(1);
-    }
+    let mut renderer = create_renderer(&options)?;
+
+    let img = renderer.render(&blueprint, &mut loader, &options)?;
+    write_output(img)?;
 
     let (resolved, total) = loader.load_counts();
 
     info!("Resolved a total of {}/{} models", resolved, total);
     info!("Total duration: {:?}", program_start_time.elapsed());
+    Ok(())
+}
+
+fn run_worker() -> Result<()> {
+    let loader = create_model_loader()?;
+    let options = render_options()?;
+    let mut renderer = create_renderer(&options)?;
+
+    info!("Worker ready, reading render jobs from stdin");
+    let mut worker = Worker::new(renderer.as_mut(), loader, options);
+    worker.run(stdin().lock(), stdout().lock())
+}
+
+fn run_batch_command(manifest: &Path, summary_path: Option<&Path>) -> Result<()> {
+    let mut loader = create_model_loader()?;
+    let options = render_options()?;
+    let mut renderer = create_renderer(&options)?;
+
+    let summary = run_batch(manifest, renderer.as_mut(), &mut loader, &options)?;
+    println!("{}", summary);
+
+    if let Some(path) = summary_path {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, &summary).map_err(std::io::Error::from)?;
+    }
+
+    if summary.failed() > 0 {
+        return Err(Error::BatchFailed {
+            failed: summary.failed(),
+            total: summary.jobs.len(),
+        });
+    }
+
+    Ok(())
+}
+
+fn run_encode(input: Option<&Path>) -> Result<()> {
+    let blueprint = Blueprint::from_json(&read_input(input)?)?;
+    println!("{}", blueprint.encode());
+    Ok(())
+}
+
+fn run_decode(input: Option<&Path>, format: DecodeFormat, diagnose: bool) -> Result<()> {
+    let blueprint = match Blueprint::decode_bytes(read_input(input)?) {
+        Ok(blueprint) => blueprint,
+        Err(err) => {
+            if diagnose {
+                let stage = err.stage();
+                match err.offset() {
+                    Some(offset) => error!(
+                        "Decoding failed in the {} stage at byte {} of the {}",
+                        stage,
+                        offset,
+                        stage.offset_context()
+                    ),
+                    None => error!("Decoding failed in the {} stage", stage),
+                }
+            }
+            return Err(err.into());
+        }
+    };
+
+    match format {
+        DecodeFormat::Json => {
+            let json = serde_json::to_string_pretty(&blueprint).map_err(std::io::Error::from)?;
+            println!("{}", json);
+        }
+        DecodeFormat::Table => print_entry_table(&blueprint),
+    }
+
+    Ok(())
+}
+
+/// Print the buildings of a blueprint as a table, with columns sized to fit their contents
+fn print_entry_table(blueprint: &Blueprint) {
+    println!("Version: {}", blueprint.version());
+    if !blueprint.islands().is_empty() {
+        println!("Islands: {}", blueprint.islands().len());
+    }
+    println!("Buildings: {}", blueprint.len());
+    println!();
+
+    let header = ["X", "Y", "LAYER", "ROTATION", "NAME", "CONFIG"].map(String::from);
+    let rows: Vec<[String; 6]> = blueprint
+        .iter()
+        .map(|entry| {
+            let (x, y, layer) = entry.tile();
+            let config = match entry.config() {
+                BuildingConfig::Empty => "-".to_string(),
+                config => config.to_string(),
+            };
+
+            [
+                x.to_string(),
+                y.to_string(),
+                layer.to_string(),
+                (entry.quarter_turns() * 90).to_string(),
+                entry.internal_name().to_string(),
+                config,
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let [x, y, layer, rotation, name, config] = row;
+        println!(
+            "{:>x_width$}  {:>y_width$}  {:>layer_width$}  {:>rotation_width$}  {:<name_width$}  {}",
+            x,
+            y,
+            layer,
+            rotation,
+            name,
+            config,
+            x_width = widths[0],
+            y_width = widths[1],
+            layer_width = widths[2],
+            rotation_width = widths[3],
+            name_width = widths[4],
+        );
+    }
+}
+
+fn run_transform(
+    input: Option<&Path>,
+    mirror: Option<MirrorAxis>,
+    rotate: i32,
+    translate: Option<[i32; 3]>,
+    normalize: bool,
+) -> Result<()> {
+    let mut blueprint = Blueprint::decode_bytes(read_input(input)?)?;
+
+    if let Some(axis) = mirror {
+        blueprint.mirror(axis.into())?;
+    }
+    if rotate != 0 {
+        blueprint.rotate(rotate)?;
+    }
+    if let Some([x, y, layer]) = translate {
+        blueprint.translate(x, y, layer)?;
+    }
+    if normalize {
+        blueprint.normalize()?;
+    }
+
+    println!("{}", blueprint.encode());
+    Ok(())
+}
+
+fn run_stats(input: Option<&Path>, format: ReportFormat) -> Result<()> {
+    let blueprint = Blueprint::decode_bytes(read_input(input)?)?;
+    let mut loader = create_model_loader()?;
+    let stats = BlueprintStats::new(&blueprint, &mut loader)?;
+
+    match format {
+        ReportFormat::Text => println!("{}", stats),
+        ReportFormat::Json => {
+            let json = serde_json::to_string_pretty(&stats).map_err(std::io::Error::from)?;
+            println!("{}", json);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_validate(input: Option<&Path>, format: ReportFormat, strict: bool) -> Result<()> {
+    let blueprint = Blueprint::decode_bytes(read_input(input)?)?;
+    let loader = create_model_loader()?;
+    let report = validate(&blueprint, &loader);
+
+    match format {
+        ReportFormat::Text => println!("{}", report),
+        ReportFormat::Json => {
+            let json = serde_json::to_string_pretty(&report).map_err(std::io::Error::from)?;
+            println!("{}", json);
+        }
+    }
+
+    if !report.passes(strict) {
+        return Err(Error::ValidationFailed {
+            errors: report.errors(),
+            warnings: report.warnings(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Read the whole of an input file, or stdin when no file is given
+fn read_input(input: Option<&Path>) -> Result<Vec<u8>> {
+    Ok(match input {
+        Some(path) => fs::read(path)?,
+        None => {
+            let mut data = Vec::new();
+            stdin().read_to_end(&mut data)?;
+            data
+        }
+    })
+}
+
+/// Parse a tile offset given as x,y or x,y,layer
+fn parse_offset(text: &str) -> std::result::Result<[i32; 3], String> {
+    let parts = text
+        .split(',')
+        .map(|part| part.trim().parse::<i32>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|err| format!("Invalid offset {:?}: {}", text, err))?;
+
+    match parts[..] {
+        [x, y] => Ok([x, y, 0]),
+        [x, y, layer] => Ok([x, y, layer]),
+        _ => Err(format!(
+            "Invalid offset {:?}: expected two or three values as x,y or x,y,layer",
+            text
+        )),
+    }
+}
+
+fn run_server(address: &str, max_size: u32) -> Result<()> {
+    let loader = create_model_loader()?;
+    let options = render_options()?;
+    let mut renderer = create_renderer(&options)?;
+
+    let mut server = RenderServer::bind(address, renderer.as_mut(), loader, options, max_size)?;
+    info!("Listening for render requests on http://{}", address);
+    server.run()
+}
+
+fn create_model_loader() -> Result<ModelLoader> {
+    let mut mapping = ModelMapping::for_model_dir(&ARGS.model_dir)?;
+    if let Some(path) = &ARGS.mapping {
+        mapping.extend(ModelMapping::read_for_model_dir(path, &ARGS.model_dir)?);
+    }
+
+    ModelLoader::with_mapping(&ARGS.model_dir, mapping)
+}
+
+/// Get the render options given on the command line, rejecting renders which would be empty or
+/// larger than [`MAX_RENDER_SIZE`].
+fn render_options() -> Result<RenderOptions> {
+    let defaults = RenderOptions::default();
+
+    let options = RenderOptions {
+        width: ARGS.width,
+        height: ARGS.height,
+        ssaa: ARGS.ssaa,
+        ssaa_sampler: ARGS.ssaa_sampler.0,
+        camera: camera_overrides().apply(&CameraOptions::default()),
+        background: ARGS.background.unwrap_or(defaults.background),
+        ground_plane: !ARGS.no_ground,
+        style: ARGS.style,
+        shadows: ARGS.shadows,
+        shadow_quality: ARGS.shadow_quality,
+        ssao: ARGS.ssao,
+        ssao_strength: ARGS.ssao_strength,
+        ssao_radius: ARGS.ssao_radius,
+    };
+
+    options.check_size(MAX_RENDER_SIZE)?;
+    Ok(options)
+}
+
+fn camera_overrides() -> CameraOverrides {
+    let camera = &ARGS.camera;
+
+    CameraOverrides {
+        view: Some(camera.view),
+        yaw: camera.yaw,
+        pitch: camera.pitch,
+        fov: Some(camera.fov),
+        padding: Some(camera.padding),
+        distance: camera.distance,
+        target: camera.target,
+        projection: camera.projection,
+    }
+}
+
+fn create_renderer(options: &RenderOptions) -> Result<Box<dyn Renderer>> {
+    Ok(match ARGS.backend {
+        Backend::Gl => {
+            let (width, height) = options.supersampled_size();
+            Box::new(GlRenderer::new(width, height, ARGS.headless)?)
+        }
+        Backend::Cpu => Box::new(SoftwareRenderer::new()),
+    })
+}
+
+fn write_output(img: RgbaImage) -> Result<()> {
+    match ARGS.out_file.as_ref() {
+        Some(path) => {
+            info!("Saving result as {}", path.display());
+            save_image(img, path)?;
+        }
+        None => {
+            info!("Writing result to stdout");
+            let buffer = encode_image(img, ImageFormat::Png)?;
+
+            let mut stdout = stdout().lock();
+            stdout.write_all(&buffer)?;
+            stdout.flush()?;
+        }
+    }
+
+    Ok(())
 }
 
 pub struct ApplicationLogger<T: LogLevel> {
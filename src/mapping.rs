@@ -0,0 +1,289 @@
+use crate::error::{Error, Result};
+use crate::render::parse_color;
+use nalgebra_glm::{rotate_x, rotate_y, rotate_z, scale, translation, Mat4, Vec3};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The mapping used when no other mapping file is provided
+const BUILT_IN_MAPPING: &str = include_str!("../models/mapping.toml");
+
+/// The names of the mapping files which are loaded from the model directory when present
+pub const MAPPING_FILE_NAMES: [&str; 2] = ["mapping.toml", "mapping.json"];
+
+/// Describes which models are used to draw each building. Buildings which are not listed use the
+/// model matching their internal name.
+///
+/// A mapping is written as a TOML or JSON table from the internal name of a building to a list of
+/// [`MappedModel`]s, such as:
+///
+/// ```toml
+/// RotatorHalfInternalVariant = [
+///     { file = "Rotator1Quad" },
+///     { file = "Rotator1QuadPlatform180", offset = [0.0, 0.05, 0.0] },
+/// ]
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct ModelMapping {
+    buildings: BTreeMap<String, Vec<MappedModel>>,
+}
+
+/// A single model used to draw a building
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MappedModel {
+    /// The name of the .obj file within the model directory, without the extension
+    pub file: String,
+    #[serde(default)]
+    pub offset: [f32; 3],
+    /// Rotations around the x, y and z axes in degrees, applied in that order
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default = "unit_scale")]
+    pub scale: [f32; 3],
+    /// The color of the model, used in place of the default blueprint color
+    #[serde(default, deserialize_with = "deserialize_color")]
+    pub color: Option<Vec3>,
+    /// The name of the material used to draw the model in the realistic style. When not given, the
+    /// material matching the file name is used.
+    #[serde(default)]
+    pub material: Option<String>,
+}
+
+/// A model referenced by a mapping which does not exist within the model directory
+#[derive(Clone, Debug)]
+pub struct MissingModel {
+    pub building: String,
+    pub file: String,
+}
+
+impl ModelMapping {
+    /// Get the mapping built into the renderer.
+    pub fn built_in() -> Self {
+        toml::from_str(BUILT_IN_MAPPING).expect("built-in mapping is valid")
+    }
+
+    /// Read a mapping file. Files with a `.json` extension are read as JSON, while all other files
+    /// are read as TOML.
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+        let mapping = if is_json {
+            serde_json::from_str(&text).map_err(|err| err.to_string())
+        } else {
+            toml::from_str(&text).map_err(|err| err.to_string())
+        };
+
+        mapping.map_err(|message| Error::Mapping {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    /// Read a mapping file given in place of part of the mapping of a model directory. Every model
+    /// referenced by the file must exist within the model directory.
+    pub fn read_for_model_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        model_dir: Q,
+    ) -> Result<Self> {
+        let mapping = Self::read_from_file(&path)?;
+
+        let missing = mapping.missing_models(model_dir);
+        if !missing.is_empty() {
+            let files: Vec<String> = missing
+                .iter()
+                .map(|missing| format!("{}.obj (used by {})", missing.file, missing.building))
+                .collect();
+
+            return Err(Error::Mapping {
+                path: path.as_ref().to_path_buf(),
+                message: format!("Missing model files {}", files.join(", ")),
+            });
+        }
+
+        Ok(mapping)
+    }
+
+    /// Get the mapping used for a model directory. This is the built-in mapping, extended by any
+    /// mapping file found within the model directory.
+    pub fn for_model_dir<P: AsRef<Path>>(model_dir: P) -> Result<Self> {
+        let mut mapping = Self::built_in();
+
+        for name in MAPPING_FILE_NAMES {
+            let path = model_dir.as_ref().join(name);
+            if path.is_file() {
+                mapping.extend(Self::read_from_file(path)?);
+            }
+        }
+
+        Ok(mapping)
+    }
+
+    /// Add the buildings of another mapping to this one, replacing any buildings mapped by both.
+    pub fn extend(&mut self, other: ModelMapping) {
+        self.buildings.extend(other.buildings);
+    }
+
+    /// Get the models used to draw a building, if the building is mapped.
+    pub fn get(&self, internal_name: &str) -> Option<&[MappedModel]> {
+        self.buildings.get(internal_name).map(Vec::as_slice)
+    }
+
+    /// Find every model referenced by this mapping which does not exist within the model directory.
+    pub fn missing_models<P: AsRef<Path>>(&self, model_dir: P) -> Vec<MissingModel> {
+        self.buildings
+            .iter()
+            .flat_map(|(building, models)| models.iter().map(move |model| (building, model)))
+            .filter(|(_, model)| find_object_file(model_dir.as_ref(), &model.file).is_none())
+            .map(|(building, model)| MissingModel {
+                building: building.clone(),
+                file: model.file.clone(),
+            })
+            .collect()
+    }
+}
+
+impl MappedModel {
+    /// Get the transform from the space of the model file into the space of the building.
+    pub fn transform(&self) -> Mat4 {
+        let [x, y, z] = self.rotation.map(f32::to_radians);
+
+        let transform = translation(&Vec3::from(self.offset));
+        let transform = rotate_z(&transform, z);
+        let transform = rotate_y(&transform, y);
+        let transform = rotate_x(&transform, x);
+        scale(&transform, &Vec3::from(self.scale))
+    }
+}
+
+/// Find the .obj file for a model name. If no file matches the full name, any `InternalVariant` or
+/// `Default` suffix is removed and the search is repeated.
+pub fn find_object_file(model_dir: &Path, mut name: &str) -> Option<PathBuf> {
+    'search: loop {
+        let path = model_dir.join(format!("{}.obj", name));
+        if path.is_file() {
+            return Some(path);
+        }
+
+        for suffix in ["InternalVariant", "Default"] {
+            if let Some(remaining) = name.strip_suffix(suffix) {
+                name = remaining;
+                continue 'search;
+            }
+        }
+
+        return None;
+    }
+}
+
+fn unit_scale() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Vec3>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(text) => match parse_color(&text) {
+            Ok(color) => Ok(Some(color.xyz())),
+            Err(msg) => Err(serde::de::Error::custom(msg)),
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/models");
+
+    /// Write a mapping file to the temporary directory, returning its path
+    fn write_mapping(name: &str, text: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("shapez2-mapping-{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_toml_mapping() {
+        let path = write_mapping(
+            "rotator.toml",
+            r##"
+            RotatorHalfInternalVariant = [
+                { file = "Rotator1Quad" },
+                { file = "Rotator1QuadPlatform180", offset = [0.0, 0.05, 0.0], color = "#ff0000" },
+            ]
+            "##,
+        );
+        let mapping = ModelMapping::read_for_model_dir(&path, MODEL_DIR).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let models = mapping.get("RotatorHalfInternalVariant").unwrap();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].file, "Rotator1Quad");
+        assert_eq!(models[0].scale, [1.0; 3]);
+        assert_eq!(models[1].offset, [0.0, 0.05, 0.0]);
+        assert_eq!(models[1].color, Some(Vec3::new(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn reads_json_mapping() {
+        let path = write_mapping(
+            "pipe.JSON",
+            r#"{"PipeForwardInternalVariant": [
+                {"file": "PipeForwardGlas", "rotation": [0, 90, 0], "material": "glass"}
+            ]}"#,
+        );
+        let mapping = ModelMapping::read_for_model_dir(&path, MODEL_DIR).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let models = mapping.get("PipeForwardInternalVariant").unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].rotation, [0.0, 90.0, 0.0]);
+        assert_eq!(models[0].material.as_deref(), Some("glass"));
+    }
+
+    #[test]
+    fn rejects_invalid_mapping() {
+        let path = write_mapping(
+            "unknown.toml",
+            r#"BeltDefaultForwardInternalVariant = [{ file = "BeltForwardReduced", size = 2 }]"#,
+        );
+        let result = ModelMapping::read_from_file(&path);
+        fs::remove_file(path).unwrap();
+
+        assert!(matches!(result, Err(Error::Mapping { .. })));
+    }
+
+    #[test]
+    fn rejects_missing_model_files() {
+        let path = write_mapping(
+            "missing.toml",
+            r#"BeltDefaultForwardInternalVariant = [{ file = "NotAModel" }, { file = "BeltForwardReduced" }]"#,
+        );
+        let result = ModelMapping::read_for_model_dir(&path, MODEL_DIR);
+        let mapping = ModelMapping::read_from_file(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        match result {
+            Err(Error::Mapping { message, .. }) => assert_eq!(
+                message,
+                "Missing model files NotAModel.obj (used by BeltDefaultForwardInternalVariant)"
+            ),
+            _ => panic!("missing model file was accepted"),
+        }
+
+        let missing = mapping.missing_models(MODEL_DIR);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].file, "NotAModel");
+    }
+}
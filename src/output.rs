@@ -0,0 +1,53 @@
+use crate::error::Result;
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use log::warn;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Encode a rendered image in the given format. The alpha channel is only kept when the image is
+/// not fully opaque and the format is able to store it.
+pub fn encode_image(img: RgbaImage, format: ImageFormat) -> Result<Vec<u8>> {
+    let img = prepare_for_format(img, format);
+
+    let mut buffer = Vec::with_capacity((img.width() * img.height() * 3) as usize);
+    img.write_to(&mut Cursor::new(&mut buffer), format)?;
+    Ok(buffer)
+}
+
+/// Save a rendered image to a file. The image format is detected from the path extension.
+pub fn save_image<P: AsRef<Path>>(img: RgbaImage, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let format = ImageFormat::from_path(path)?;
+    prepare_for_format(img, format).save_with_format(path, format)?;
+    Ok(())
+}
+
+fn prepare_for_format(img: RgbaImage, format: ImageFormat) -> DynamicImage {
+    let is_opaque = img.pixels().all(|pixel| pixel.0[3] == u8::MAX);
+    if !is_opaque && supports_alpha(format) {
+        return DynamicImage::ImageRgba8(img);
+    }
+
+    if !is_opaque {
+        warn!(
+            "{:?} images do not support transparency, the alpha channel will be dropped",
+            format
+        );
+    }
+
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(img).into_rgb8())
+}
+
+fn supports_alpha(format: ImageFormat) -> bool {
+    matches!(
+        format,
+        ImageFormat::Png
+            | ImageFormat::WebP
+            | ImageFormat::Tiff
+            | ImageFormat::Tga
+            | ImageFormat::Ico
+            | ImageFormat::Bmp
+            | ImageFormat::OpenExr
+            | ImageFormat::Qoi
+    )
+}
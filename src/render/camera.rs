@@ -0,0 +1,248 @@
+use crate::render::scene::Aabb;
+use clap::ValueEnum;
+use nalgebra_glm::{look_at, ortho, perspective, rotate_y_vec3, Mat4, Vec3};
+use serde::Deserialize;
+
+/// The vertical field of view used when none is specified, in degrees
+pub const DEFAULT_FOV: f32 = 34.37747;
+
+/// The projection used to map the scene onto the image
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Projection {
+    /// Distant objects are drawn smaller
+    #[default]
+    Perspective,
+    /// Objects are drawn the same size at any distance
+    #[serde(alias = "ortho")]
+    #[value(alias = "ortho")]
+    Orthographic,
+}
+
+/// A named set of camera settings
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewPreset {
+    /// A perspective view looking down at 45 degrees towards the longest side of the blueprint
+    #[default]
+    Default,
+    /// A true isometric view, where the horizontal axes are drawn at 30 degrees
+    #[serde(alias = "iso")]
+    #[value(alias = "iso")]
+    Isometric,
+    /// A plan view looking straight down, oriented the same way as the default view
+    Top,
+    /// An elevation view looking along the z axis
+    Front,
+    /// An elevation view looking along the x axis
+    Side,
+}
+
+impl ViewPreset {
+    /// Get the camera settings for this preset. With the exception of the default view, presets
+    /// center the camera on the blueprint so it fills as much of the image as possible.
+    pub fn camera_options(self) -> CameraOptions {
+        let orthographic = |yaw: Option<f32>, pitch: f32| CameraOptions {
+            yaw,
+            pitch,
+            target: None,
+            projection: Projection::Orthographic,
+            ..CameraOptions::default()
+        };
+
+        match self {
+            ViewPreset::Default => CameraOptions::default(),
+            // The angle at which the diagonal of a cube is perpendicular to the image
+            ViewPreset::Isometric => {
+                orthographic(Some(-45.0), 1f32.atan2(2f32.sqrt()).to_degrees())
+            }
+            // Keep the automatic yaw so the plan matches the orientation of the default view
+            ViewPreset::Top => orthographic(None, 90.0),
+            ViewPreset::Front => orthographic(Some(0.0), 0.0),
+            ViewPreset::Side => orthographic(Some(90.0), 0.0),
+        }
+    }
+}
+
+/// Settings controlling where the camera is placed. All angles are given in degrees.
+#[derive(Copy, Clone, Debug)]
+pub struct CameraOptions {
+    /// The rotation of the camera around the vertical axis. If not provided, the camera is turned
+    /// to face the longest side of the blueprint.
+    pub yaw: Option<f32>,
+    /// The angle of the camera above the horizon. A pitch of 90 looks straight down.
+    pub pitch: f32,
+    /// The vertical field of view of a perspective projection
+    pub fov: f32,
+    /// The fraction of extra space left around the blueprint when fitting it to the image. Negative
+    /// values zoom in past the edges of the blueprint.
+    pub padding: f32,
+    /// The distance between the camera and the target. If not provided, the distance is chosen so
+    /// the entire blueprint is visible.
+    pub distance: Option<f32>,
+    /// The point the camera looks at. If not provided, the camera looks at the center of the
+    /// blueprint.
+    pub target: Option<Vec3>,
+    pub projection: Projection,
+}
+
+impl Default for CameraOptions {
+    fn default() -> Self {
+        CameraOptions {
+            yaw: None,
+            pitch: 45.0,
+            fov: DEFAULT_FOV,
+            padding: 0.05,
+            distance: None,
+            target: Some(Vec3::zeros()),
+            projection: Projection::Perspective,
+        }
+    }
+}
+
+/// Changes to apply on top of a set of [`CameraOptions`]. This is used by render jobs which only
+/// override some of the camera settings.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraOverrides {
+    /// A preset to use in place of the base settings. The remaining overrides are applied on top
+    /// of the preset.
+    pub view: Option<ViewPreset>,
+    pub yaw: Option<f32>,
+    pub pitch: Option<f32>,
+    pub fov: Option<f32>,
+    pub padding: Option<f32>,
+    pub distance: Option<f32>,
+    pub target: Option<[f32; 3]>,
+    pub projection: Option<Projection>,
+}
+
+impl CameraOverrides {
+    /// Get the given options with these overrides applied.
+    pub fn apply(&self, options: &CameraOptions) -> CameraOptions {
+        let preset = self.view.map(ViewPreset::camera_options);
+        let options = preset.as_ref().unwrap_or(options);
+
+        CameraOptions {
+            yaw: self.yaw.or(options.yaw),
+            pitch: self.pitch.unwrap_or(options.pitch),
+            fov: self.fov.unwrap_or(options.fov),
+            padding: self.padding.unwrap_or(options.padding),
+            distance: self.distance.or(options.distance),
+            target: self.target.map(Vec3::from).or(options.target),
+            projection: self.projection.unwrap_or(options.projection),
+        }
+    }
+}
+
+/// Parse a point written as `x,y,z`.
+pub fn parse_point(text: &str) -> Result<[f32; 3], String> {
+    let components = text
+        .split(',')
+        .map(|component| component.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("Invalid point {:?}: {}", text, err))?;
+
+    <[f32; 3]>::try_from(components)
+        .map_err(|_| format!("Invalid point {:?}: expected three values as x,y,z", text))
+}
+
+/// The camera used to view the scene
+pub struct Camera {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub position: Vec3,
+}
+
+impl Camera {
+    /// Position a camera so the entire bounding box is visible within a render of the given size.
+    /// Only the part of the bounding box above `y = 0` is fit to the image, while the ground at
+    /// `ground_height` is kept within the view of orthographic projections.
+    pub fn fit_to_aabb(
+        aabb: Aabb,
+        ground_height: f32,
+        width: u32,
+        height: u32,
+        options: &CameraOptions,
+    ) -> Self {
+        let yaw = match options.yaw {
+            Some(yaw) => yaw.to_radians(),
+            // Face the longest side of the blueprint
+            None if aabb.max.x - aabb.min.x < aabb.max.z - aabb.min.z => 90f32.to_radians(),
+            None => 0.0,
+        };
+        let pitch = options.pitch.clamp(-90.0, 90.0).to_radians();
+
+        // The direction from the target towards the camera, and the camera up vector
+        let view_vector = rotate_y_vec3(&Vec3::new(0.0, pitch.sin(), -pitch.cos()), -yaw);
+        let up = rotate_y_vec3(&Vec3::new(0.0, pitch.cos(), pitch.sin()), -yaw);
+
+        let aspect_ratio = width as f32 / height as f32;
+        let fovy = options.fov.clamp(1.0, 179.0).to_radians();
+        let padding = 1.0 + options.padding;
+
+        // Clip AABB to above ground level
+        let mut aabb = aabb;
+        aabb.min.y = f32::max(aabb.min.y, 0.0);
+        aabb.max.y = f32::max(aabb.max.y, 0.0);
+
+        let target = options.target.unwrap_or((aabb.min + aabb.max) / 2.0);
+        let camera_space_aabb =
+            aabb.apply_transform(&look_at(&target, &(target - view_vector), &up));
+
+        let x_extent = f32::max(camera_space_aabb.min.x.abs(), camera_space_aabb.max.x);
+        let y_extent = f32::max(camera_space_aabb.min.y.abs(), camera_space_aabb.max.y);
+
+        let (t, projection) = match options.projection {
+            Projection::Perspective => {
+                let fovx = aspect_ratio * fovy;
+                let min_x_t = x_extent / (fovx / 2.0).tan();
+                let min_y_t = y_extent / (fovy / 2.0).tan();
+                let t = options.distance.unwrap_or_else(|| {
+                    padding * (f32::max(min_x_t, min_y_t) + camera_space_aabb.min.z.abs())
+                });
+
+                let extension = fovy.tan() / fovy.sin();
+                let far = 2.0 * extension * (t + camera_space_aabb.max.z);
+                (t, perspective(aspect_ratio, fovy, 0.1, far))
+            }
+            Projection::Orthographic => {
+                let half_height = padding * f32::max(y_extent, x_extent / aspect_ratio);
+                let half_width = half_height * aspect_ratio;
+
+                // The camera distance does not change the image, it only needs to be far enough
+                // away to not clip the nearest point. When looking down, the camera is also moved
+                // back until the entire near plane is above the ground.
+                let target_height = target.y - ground_height;
+                let mut t = camera_space_aabb.max.z.max(0.0) + 1.0;
+                if pitch.sin() > 0.01 {
+                    let bottom_edge_drop = half_height * pitch.cos() - target_height;
+                    t = t.max(bottom_edge_drop / pitch.sin() + 1.0);
+                }
+                let t = options.distance.unwrap_or(t);
+
+                // Extend the far plane to where the top edge of the view meets the ground, so the
+                // ground plane covers the entire image
+                let mut far = t - camera_space_aabb.min.z + 1.0;
+                if pitch.sin() > 0.01 {
+                    let top_edge_height =
+                        target_height + t * pitch.sin() + half_height * pitch.cos();
+                    far = far.max(1.01 * top_edge_height / pitch.sin());
+                }
+
+                let projection =
+                    ortho(-half_width, half_width, -half_height, half_height, 0.1, far);
+                (t, projection)
+            }
+        };
+
+        let position = target + t * view_vector;
+        let view = look_at(&position, &target, &up);
+
+        Camera {
+            view,
+            projection,
+            position,
+        }
+    }
+}
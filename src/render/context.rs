@@ -1,5 +1,10 @@
+use crate::error::{Error, Result};
+use crate::render::framebuffer::OffscreenFramebuffer;
+use crate::render::gl;
 use crate::render::gl::Gl;
-use glutin::config::{Config, ConfigTemplateBuilder, GlConfig};
+use glutin::config::{
+    ColorBufferType, Config, ConfigSurfaceTypes, ConfigTemplateBuilder, GlConfig,
+};
 use glutin::context::{
     ContextApi, ContextAttributesBuilder, NotCurrentContext, NotCurrentGlContextSurfaceAccessor,
     PossiblyCurrentContext, Version,
@@ -7,24 +12,130 @@ use glutin::context::{
 use glutin::display::{Display, GetGlDisplay, GlDisplay};
 use glutin::surface::{Surface, WindowSurface};
 use glutin_winit::{DisplayBuilder, GlWindow};
-use log::{error, info, warn};
+use log::{info, warn};
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use std::ffi::CString;
 use std::ops::Deref;
-use std::process::exit;
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::time::Instant;
 use winit::dpi::PhysicalSize;
 use winit::event_loop::EventLoop;
 use winit::window::{Window, WindowBuilder};
 
 pub struct DisplayManager {
-    pub window: Window,
+    pub target: RenderTarget,
     pub gl_config: Config,
-    pub gl_surface: Surface<WindowSurface>,
+    // The context is never accessed directly, but it must be kept alive for as long as `gl` is used
+    #[allow(dead_code)]
     pub gl_context: PossiblyCurrentContext,
     pub gl: Gl,
 }
 
+pub enum RenderTarget {
+    /// Render to the default framebuffer of a hidden window. This requires a display server (or
+    /// xvfb) with a screen large enough to fit the render.
+    Window {
+        window: Window,
+        #[allow(dead_code)]
+        surface: Surface<WindowSurface>,
+    },
+    /// Render to an offscreen framebuffer on a context which has no surface.
+    Offscreen(OffscreenFramebuffer),
+}
+
+impl DisplayManager {
+    /// The largest render size which can be drawn to the current render target.
+    pub fn max_render_size(&self) -> (u32, u32) {
+        match &self.target {
+            RenderTarget::Window { window, .. } => {
+                let size = window.inner_size();
+                (size.width, size.height)
+            }
+            RenderTarget::Offscreen(framebuffer) => (framebuffer.width, framebuffer.height),
+        }
+    }
+
+    /// The number of MSAA samples performed for each pixel of the render target.
+    pub fn msaa_samples(&self) -> u32 {
+        match &self.target {
+            RenderTarget::Window { .. } => self.gl_config.num_samples().max(1) as u32,
+            RenderTarget::Offscreen(framebuffer) => framebuffer.samples,
+        }
+    }
+
+    /// The number of bits used to store each color channel of the render target.
+    pub fn color_sample_size(&self) -> u8 {
+        if let RenderTarget::Offscreen(_) = &self.target {
+            return 8;
+        }
+
+        match self.gl_config.color_buffer_type() {
+            Some(ColorBufferType::Rgb {
+                r_size,
+                g_size,
+                b_size,
+            }) => r_size.max(g_size).max(b_size),
+            Some(ColorBufferType::Luminance(size)) => size,
+            None => 8,
+        }
+    }
+
+    /// Grow the render target so it can hold a render of the given size. Only offscreen render
+    /// targets can be resized; the size of a window is fixed once it has been created.
+    ///
+    /// # Safety
+    /// The graphics context must be current on the calling thread.
+    pub unsafe fn reserve_render_size(&mut self, width: u32, height: u32) -> Result<()> {
+        if let RenderTarget::Offscreen(framebuffer) = &self.target {
+            if width <= framebuffer.width && height <= framebuffer.height {
+                return Ok(());
+            }
+
+            let width = width.max(framebuffer.width);
+            let height = height.max(framebuffer.height);
+            let framebuffer = OffscreenFramebuffer::new(&self.gl, width, height)?;
+
+            if let RenderTarget::Offscreen(previous) =
+                std::mem::replace(&mut self.target, RenderTarget::Offscreen(framebuffer))
+            {
+                previous.delete(&self.gl);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bind the render target so it receives all following draw calls.
+    ///
+    /// # Safety
+    /// The graphics context must be current on the calling thread.
+    pub unsafe fn bind_for_drawing(&self) {
+        match &self.target {
+            RenderTarget::Window { .. } => bind_window_framebuffer(&self.gl),
+            RenderTarget::Offscreen(framebuffer) => framebuffer.bind_for_drawing(&self.gl),
+        }
+    }
+
+    /// Bind the render target so it can be read back with glReadPixels.
+    ///
+    /// # Safety
+    /// The graphics context must be current on the calling thread.
+    pub unsafe fn bind_for_reading(&self) {
+        match &self.target {
+            RenderTarget::Window { .. } => bind_window_framebuffer(&self.gl),
+            RenderTarget::Offscreen(framebuffer) => framebuffer.bind_for_reading(&self.gl),
+        }
+    }
+}
+
+/// Bind the default framebuffer of the window for both drawing and reading. Other passes (such as
+/// the shadow map) leave their own framebuffers bound, so this can not be skipped.
+unsafe fn bind_window_framebuffer(gl: &Gl) {
+    gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+    gl.BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+}
+
 impl Deref for DisplayManager {
     type Target = Gl;
 
@@ -33,29 +144,23 @@ impl Deref for DisplayManager {
     }
 }
 
-fn pick_gl_config(config_iter: Box<dyn Iterator<Item = Config> + '_>) -> Config {
-    let config = config_iter.reduce(|best, next| {
+const NO_CONFIGS_AVAILABLE: &str = "No 3D display configurations available on this system";
+
+fn pick_gl_config(config_iter: Box<dyn Iterator<Item = Config> + '_>) -> Option<Config> {
+    config_iter.reduce(|best, next| {
         if next.num_samples() > best.num_samples() {
             next
         } else {
             best
         }
-    });
-
-    match config {
-        Some(x) => x,
-        None => {
-            error!("No 3D display configurations available on this system");
-            exit(1);
-        }
-    }
+    })
 }
 
 unsafe fn create_context(
     config: &Config,
     display: &Display,
     raw_window_handle: Option<RawWindowHandle>,
-) -> NotCurrentContext {
+) -> Result<NotCurrentContext> {
     // The context creation part. It can be created before surface and that's how
     // it's expected in multithreaded + multiwindow operation mode, since you
     // can send NotCurrentContext, but not Surface.
@@ -63,7 +168,7 @@ unsafe fn create_context(
     let context_attributes = ContextAttributesBuilder::new().build(raw_window_handle);
 
     match display.create_context(config, &context_attributes) {
-        Ok(context) => return context,
+        Ok(context) => return Ok(context),
         Err(err) => warn!("Failed to create OpenGL core context: {}", err),
     }
 
@@ -75,7 +180,7 @@ unsafe fn create_context(
         .build(raw_window_handle);
 
     match display.create_context(config, &fallback_context_attributes) {
-        Ok(context) => return context,
+        Ok(context) => return Ok(context),
         Err(err) => warn!("Failed to create OpenGL core context: {}", err),
     }
 
@@ -86,16 +191,17 @@ unsafe fn create_context(
         .with_context_api(ContextApi::OpenGl(Some(Version::new(2, 1))))
         .build(raw_window_handle);
 
-    match display.create_context(config, &legacy_context_attributes) {
-        Ok(context) => context,
-        Err(err) => {
-            error!("Failed to create OpenGL 2.1 (legacy) context: {}", err);
-            exit(1)
-        }
-    }
+    display
+        .create_context(config, &legacy_context_attributes)
+        .map_err(|err| {
+            Error::Context(format!(
+                "Failed to create OpenGL 2.1 (legacy) context: {}",
+                err
+            ))
+        })
 }
 
-pub fn setup_opengl(width: u32, height: u32) -> (EventLoop<()>, DisplayManager) {
+pub fn setup_opengl(width: u32, height: u32) -> Result<(EventLoop<()>, DisplayManager)> {
     info!("Creating graphics context");
     let context_setup_start_time = Instant::now();
 
@@ -110,58 +216,155 @@ pub fn setup_opengl(width: u32, height: u32) -> (EventLoop<()>, DisplayManager)
     let display_builder = DisplayBuilder::new();
     let display_builder = display_builder.with_window_builder(Some(window_context));
 
-    let (window, gl_config) = match display_builder.build(&event_loop, template, pick_gl_config) {
-        Ok(v) => v,
-        Err(e) => {
-            error!("Failed to find graphics configuration: {}", e);
-            exit(1);
+    // The config picker used by glutin-winit must always return a config, so the only way to
+    // handle a system without any configurations is to unwind out of the picker.
+    let build_result = panic::catch_unwind(AssertUnwindSafe(|| {
+        display_builder.build(&event_loop, template, |configs| {
+            pick_gl_config(configs).expect(NO_CONFIGS_AVAILABLE)
+        })
+    }));
+
+    let (window, gl_config) = match build_result {
+        Ok(Ok(v)) => v,
+        Ok(Err(e)) => {
+            return Err(Error::Context(format!(
+                "Failed to find graphics configuration: {}",
+                e
+            )))
         }
+        Err(_) => return Err(Error::Context(NO_CONFIGS_AVAILABLE.to_string())),
     };
 
     info!("Picked a GL config with {}xMSAA", gl_config.num_samples());
 
-    let window = match window {
-        Some(x) => x,
-        None => {
-            error!("Failed to create hidden window");
-            exit(1);
-        }
-    };
+    let window = window.ok_or_else(|| Error::Context("Failed to create hidden window".into()))?;
     let raw_window_handle = Some(window.raw_window_handle());
 
     // The display could be obtained from the any object created by it, so we
     // can query it from the config.
     let gl_display = gl_config.display();
     let not_current_gl_context =
-        unsafe { create_context(&gl_config, &gl_display, raw_window_handle) };
+        unsafe { create_context(&gl_config, &gl_display, raw_window_handle)? };
 
     let attrs = window.build_surface_attributes(Default::default());
-    let gl_surface = match unsafe { gl_display.create_window_surface(&gl_config, &attrs) } {
-        Ok(surface) => surface,
-        Err(err) => {
-            error!("Failed to create window GL surface: {}", err);
-            exit(1);
-        }
+    let gl_surface = unsafe { gl_display.create_window_surface(&gl_config, &attrs) }
+        .map_err(|err| Error::Context(format!("Failed to create window GL surface: {}", err)))?;
+
+    let gl_context = not_current_gl_context
+        .make_current(&gl_surface)
+        .map_err(|err| Error::Context(format!("Failed to make context current: {}", err)))?;
+
+    let gl = load_gl(&gl_display);
+
+    let display_manager = DisplayManager {
+        target: RenderTarget::Window {
+            window,
+            surface: gl_surface,
+        },
+        gl_config,
+        gl_context,
+        gl,
     };
 
-    let gl_context = not_current_gl_context.make_current(&gl_surface).unwrap();
+    info!(
+        "Setup graphics context in {:?}",
+        context_setup_start_time.elapsed()
+    );
+    Ok((event_loop, display_manager))
+}
 
-    let gl = Gl::load_with(|symbol| {
+fn load_gl(gl_display: &Display) -> Gl {
+    Gl::load_with(|symbol| {
         let symbol = CString::new(symbol).expect("symbols do not contain null terminators");
         gl_display.get_proc_address(symbol.as_c_str()).cast()
+    })
+}
+
+/// Create a graphics context without a window or display server by using EGL on top of a device
+/// directly. Rendering is then performed into an offscreen framebuffer of the requested size.
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+pub fn setup_headless_opengl(width: u32, height: u32) -> Result<DisplayManager> {
+    use glutin::api::egl::device::Device;
+    use glutin::api::egl::display::Display as EglDisplay;
+
+    info!("Creating headless graphics context");
+    let context_setup_start_time = Instant::now();
+
+    let devices = Device::query_devices()
+        .map_err(|err| Error::Context(format!("Unable to query EGL devices: {}", err)))?
+        .collect::<Vec<_>>();
+
+    let egl_display = devices.iter().find_map(|device| {
+        info!(
+            "Attempting to use EGL device {} ({})",
+            device.name().unwrap_or("unknown"),
+            device.vendor().unwrap_or("unknown vendor")
+        );
+
+        match unsafe { EglDisplay::with_device(device, None) } {
+            Ok(display) => Some(display),
+            Err(err) => {
+                warn!("Failed to create EGL display: {}", err);
+                None
+            }
+        }
     });
 
+    let gl_display = match egl_display {
+        Some(display) => Display::Egl(display),
+        None => {
+            return Err(Error::Context(
+                "No EGL devices are available for headless rendering".into(),
+            ))
+        }
+    };
+
+    // The config is only used for context creation, so we do not need it to support any surface
+    // types or provide multisampling.
+    let template = ConfigTemplateBuilder::new()
+        .with_surface_type(ConfigSurfaceTypes::empty())
+        .build();
+
+    let configs = unsafe { gl_display.find_configs(template) }
+        .map_err(|err| Error::Context(format!("Failed to find graphics configuration: {}", err)))?;
+    let gl_config =
+        pick_gl_config(configs).ok_or_else(|| Error::Context(NO_CONFIGS_AVAILABLE.into()))?;
+
+    let not_current_gl_context = unsafe { create_context(&gl_config, &gl_display, None)? };
+    let gl_context = match not_current_gl_context {
+        NotCurrentContext::Egl(context) => match context.make_current_surfaceless() {
+            Ok(context) => PossiblyCurrentContext::Egl(context),
+            Err(err) => {
+                return Err(Error::Context(format!(
+                    "Failed to make surfaceless context current: {}",
+                    err
+                )))
+            }
+        },
+        #[allow(unreachable_patterns)]
+        _ => unreachable!("context was created on an EGL display"),
+    };
+
+    let gl = load_gl(&gl_display);
+    let framebuffer = unsafe { OffscreenFramebuffer::new(&gl, width, height)? };
+
     let display_manager = DisplayManager {
-        window,
+        target: RenderTarget::Offscreen(framebuffer),
         gl_config,
-        gl_surface,
         gl_context,
         gl,
     };
 
     info!(
-        "Setup graphics context in {:?}",
+        "Setup headless graphics context in {:?}",
         context_setup_start_time.elapsed()
     );
-    (event_loop, display_manager)
+    Ok(display_manager)
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn setup_headless_opengl(_width: u32, _height: u32) -> Result<DisplayManager> {
+    Err(Error::Context(
+        "Headless rendering requires EGL, which is not available on this platform".into(),
+    ))
 }
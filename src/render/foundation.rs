@@ -0,0 +1,230 @@
+use crate::blueprint::{IslandEntry, ISLAND_CHUNK_SIZE};
+use log::warn;
+use nalgebra_glm::{rotate_y, scale, translate, translation, Mat4, Vec3};
+use num_traits::FloatConst;
+use std::collections::BTreeSet;
+
+/// A model making up part of the foundation of an island
+pub struct FoundationPiece {
+    pub model: &'static str,
+    /// The world transform of the model
+    pub transform: Mat4,
+}
+
+/// Get the chunks covered by an island layout, relative to the origin chunk of the island.
+fn layout_chunks(layout: &str) -> Option<&'static [(i32, i32)]> {
+    Some(match layout {
+        "Layout_1" => &[(0, 0)],
+        "Layout_2" => &[(0, 0), (1, 0)],
+        "Layout_3_L" => &[(0, 0), (1, 0), (0, 1)],
+        "Layout_4_T" => &[(0, 0), (1, 0), (2, 0), (1, 1)],
+        "Layout_4_Quad_TwoNotches" => &[(0, 0), (1, 0), (0, 1), (1, 1)],
+        "Layout_5_Cross" => &[(1, 0), (0, 1), (1, 1), (2, 1), (1, 2)],
+        "Layout_9_Quad_TopAllNotches" => &[
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (0, 2),
+            (1, 2),
+            (2, 2),
+        ],
+        _ => return None,
+    })
+}
+
+/// The directions along the blueprint axes, in the order of the quarter turns which face a model
+/// towards them
+const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+/// Get the name of the floor tile model with edges facing the given directions. The letters of a
+/// tile name refer to the axes of the model, where the top (T) faces the -y axis of the blueprint.
+fn tile_model(exposed: [bool; 4]) -> &'static str {
+    match exposed {
+        [false, false, false, false] => "Tile_N",
+        [true, false, false, false] => "Tile_T",
+        [false, true, false, false] => "Tile_R",
+        [false, false, true, false] => "Tile_B",
+        [false, false, false, true] => "Tile_L",
+        [true, false, true, false] => "Tile_TB",
+        [false, true, false, true] => "Tile_RL",
+        [true, true, false, false] => "Tile_TR",
+        [false, true, true, false] => "Tile_RB",
+        [false, false, true, true] => "Tile_BL",
+        [true, false, false, true] => "Tile_LT",
+        [true, true, true, false] => "Tile_TRB",
+        [false, true, true, true] => "Tile_RBL",
+        [true, false, true, true] => "Tile_BLT",
+        [true, true, false, true] => "Tile_LTR",
+        [true, true, true, true] => "Tile_TRBL",
+    }
+}
+
+/// Builds the pieces of an island in the space of the island
+struct FoundationBuilder {
+    island_transform: Mat4,
+    pieces: Vec<FoundationPiece>,
+}
+
+impl FoundationBuilder {
+    /// Place a model at a position given in tiles, turned by a number of quarter turns the same
+    /// way buildings are
+    fn place(&mut self, model: &'static str, x: f32, y: f32, quarter_turns: i32, size: Vec3) {
+        let transform = translate(&self.island_transform, &Vec3::new(x, 0.0, y));
+        let transform = scale(&transform, &Vec3::new(1.0, 1.0, -1.0));
+        let transform = rotate_y(&transform, quarter_turns as f32 * f32::FRAC_PI_2());
+        let transform = scale(&transform, &size);
+
+        self.pieces.push(FoundationPiece { model, transform });
+    }
+}
+
+/// Build the foundation of an island from the tile, wall and frame models. Each chunk is covered
+/// by floor tiles and a station frame. Exposed chunk edges are lined with walls with a notch in
+/// their center, and corners are filled depending on the surrounding chunks.
+pub fn island_foundation(island: &IslandEntry) -> Vec<FoundationPiece> {
+    let chunks: BTreeSet<(i32, i32)> = match layout_chunks(island.layout()) {
+        Some(chunks) => chunks.iter().copied().collect(),
+        None => {
+            warn!(
+                "Unknown island layout {}; drawing a single chunk",
+                island.layout()
+            );
+            BTreeSet::from([(0, 0)])
+        }
+    };
+
+    // Rotate about the center of the origin chunk, which lies between the tiles -1 and 0
+    let (chunk_x, chunk_y) = island.chunk_position();
+    let origin = Vec3::new(
+        (chunk_x * ISLAND_CHUNK_SIZE) as f32 - 0.5,
+        0.0,
+        (chunk_y * ISLAND_CHUNK_SIZE) as f32 - 0.5,
+    );
+    let island_transform = translation(&origin);
+    let island_transform = rotate_y(
+        &island_transform,
+        -(island.quarter_turns() as f32) * f32::FRAC_PI_2(),
+    );
+    let island_transform = translate(&island_transform, &Vec3::new(0.5, 0.0, 0.5));
+
+    let mut builder = FoundationBuilder {
+        island_transform,
+        pieces: Vec::new(),
+    };
+
+    let size = ISLAND_CHUNK_SIZE;
+    let half = size / 2;
+    let full_size = Vec3::new(1.0, 1.0, 1.0);
+
+    for &(cx, cy) in &chunks {
+        let (min_x, min_y) = (cx * size - half, cy * size - half);
+        let center = ((min_x + half) as f32 - 0.5, (min_y + half) as f32 - 0.5);
+        builder.place("StationFrameBase", center.0, center.1, 0, full_size);
+
+        // Floor tiles, with edges on the border of the island
+        for x in min_x..min_x + size {
+            for y in min_y..min_y + size {
+                let exposed = DIRECTIONS.map(|(dx, dy)| {
+                    let (nx, ny) = (x + dx, y + dy);
+                    let neighbor = ((nx + half).div_euclid(size), (ny + half).div_euclid(size));
+                    !chunks.contains(&neighbor)
+                });
+                builder.place(tile_model(exposed), x as f32, y as f32, 0, full_size);
+            }
+        }
+
+        // Walls and notches along each exposed edge, leaving the outermost tile of each end for
+        // the corners
+        for (quarter_turns, (dx, dy)) in DIRECTIONS.into_iter().enumerate() {
+            if chunks.contains(&(cx + dx, cy + dy)) {
+                continue;
+            }
+
+            let edge_pieces = [
+                ("Wall_6m_1", 3.5),
+                ("Notch_6m", 9.5),
+                ("Wall_6m_1", 15.5),
+                ("Wall_6m_2nd_1", 3.5),
+                ("Wall_6m_2nd_1", 9.5),
+                ("Wall_6m_2nd_1", 15.5),
+            ];
+            for (model, offset) in edge_pieces {
+                let (x, y) = match (dx, dy) {
+                    (0, _) => (
+                        min_x as f32 + offset,
+                        if dy < 0 { min_y } else { min_y + size - 1 } as f32,
+                    ),
+                    _ => (
+                        if dx < 0 { min_x } else { min_x + size - 1 } as f32,
+                        min_y as f32 + offset,
+                    ),
+                };
+                builder.place(model, x, y, quarter_turns as i32, full_size);
+            }
+        }
+    }
+
+    // Fill in each chunk corner depending on which of the four surrounding chunks are present
+    let corners: BTreeSet<(i32, i32)> = chunks
+        .iter()
+        .flat_map(|&(cx, cy)| [(cx, cy), (cx + 1, cy), (cx, cy + 1), (cx + 1, cy + 1)])
+        .collect();
+
+    for (vx, vy) in corners {
+        // The chunks touching the corner, listed by the direction from the corner towards them
+        let quadrants = [(-1, -1), (1, -1), (1, 1), (-1, 1)];
+        let occupied = quadrants.map(|(sx, sy)| {
+            let chunk = (vx + (sx - 1) / 2, vy + (sy - 1) / 2);
+            chunks.contains(&chunk)
+        });
+
+        let corner_x = (vx * size - half) as f32 - 0.5;
+        let corner_y = (vy * size - half) as f32 - 0.5;
+
+        match occupied.iter().filter(|&&x| x).count() {
+            // Convex corners of each chunk touching the corner only diagonally
+            1 | 2 if !(0..4).any(|i| occupied[i] && occupied[(i + 1) % 4]) => {
+                for (i, &(sx, sy)) in quadrants.iter().enumerate() {
+                    if occupied[i] {
+                        let x = corner_x + 0.5 * sx as f32;
+                        let y = corner_y + 0.5 * sy as f32;
+                        let quarter_turns = (i as i32 + 1) % 4;
+                        builder.place("Corner_Convex_1", x, y, quarter_turns, full_size);
+                        builder.place("Corner_Convex_2nd_1", x, y, quarter_turns, full_size);
+                    }
+                }
+            }
+            // A straight edge continuing into a neighboring chunk
+            2 => {
+                let i = (0..4)
+                    .find(|&i| occupied[i] && occupied[(i + 1) % 4])
+                    .expect("occupied chunks are adjacent");
+                // The side facing away from both chunks
+                let quarter_turns = (i as i32 + 2) % 4;
+                let (dx, dy) = DIRECTIONS[quarter_turns as usize];
+                let x = corner_x - 0.5 * dx as f32;
+                let y = corner_y - 0.5 * dy as f32;
+                for model in ["Wall_4m_2", "Wall_4m_2nd_4"] {
+                    builder.place(model, x, y, quarter_turns, Vec3::new(0.5, 1.0, 1.0));
+                }
+            }
+            // An inner corner facing the only missing chunk
+            3 => {
+                let i = (0..4)
+                    .find(|&i| !occupied[i])
+                    .expect("one chunk is missing");
+                let (sx, sy) = quadrants[i];
+                let x = corner_x - 0.5 * sx as f32;
+                let y = corner_y - 0.5 * sy as f32;
+                builder.place("Corner_Concave_1", x, y, i as i32, full_size);
+                builder.place("Corner_Concave_2nd_1", x, y, i as i32, full_size);
+            }
+            _ => {}
+        }
+    }
+
+    builder.pieces
+}
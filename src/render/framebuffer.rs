@@ -0,0 +1,176 @@
+use crate::error::Result;
+use crate::render::gl::types::{GLint, GLsizei, GLuint};
+use crate::render::util::check_for_errors;
+use crate::render::{gl, Gl};
+use log::{info, warn};
+
+/// A framebuffer object backed by color and depth renderbuffers. This is used as the render target
+/// when no window surface is available. If the driver supports multisampling, the scene is drawn
+/// into a multisampled framebuffer and resolved into a single sampled one before being read back.
+pub struct OffscreenFramebuffer {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    draw_framebuffer: GLuint,
+    resolve_framebuffer: Option<GLuint>,
+    renderbuffers: Vec<GLuint>,
+}
+
+impl OffscreenFramebuffer {
+    pub unsafe fn new(gl: &Gl, width: u32, height: u32) -> Result<Self> {
+        let mut max_renderbuffer_size: GLint = 0;
+        gl.GetIntegerv(gl::MAX_RENDERBUFFER_SIZE, &mut max_renderbuffer_size);
+
+        let mut max_viewport_dims: [GLint; 2] = [0; 2];
+        gl.GetIntegerv(gl::MAX_VIEWPORT_DIMS, max_viewport_dims.as_mut_ptr());
+
+        let max_width = max_renderbuffer_size.min(max_viewport_dims[0]).max(1) as u32;
+        let max_height = max_renderbuffer_size.min(max_viewport_dims[1]).max(1) as u32;
+
+        let (width, height) = if width > max_width || height > max_height {
+            warn!(
+                "Requested framebuffer size ({}, {}) exceeds the maximum supported size ({}, {})",
+                width, height, max_width, max_height
+            );
+            super::clamp_with_aspect_ratio(width, height, max_width, max_height)
+        } else {
+            (width, height)
+        };
+
+        let mut max_samples: GLint = 0;
+        gl.GetIntegerv(gl::MAX_SAMPLES, &mut max_samples);
+        let samples = max_samples.max(1) as u32;
+
+        info!(
+            "Creating offscreen framebuffer of size ({}, {}) with {}xMSAA",
+            width, height, samples
+        );
+
+        let mut renderbuffers = Vec::with_capacity(4);
+        let draw_framebuffer = create_framebuffer(gl, width, height, samples, &mut renderbuffers);
+        let resolve_framebuffer = match samples {
+            1 => None,
+            _ => Some(create_framebuffer(gl, width, height, 1, &mut renderbuffers)),
+        };
+
+        gl.BindFramebuffer(gl::FRAMEBUFFER, draw_framebuffer);
+        check_for_errors(gl)?;
+
+        Ok(OffscreenFramebuffer {
+            width,
+            height,
+            samples,
+            draw_framebuffer,
+            resolve_framebuffer,
+            renderbuffers,
+        })
+    }
+
+    /// Release the graphics memory held by this framebuffer.
+    pub unsafe fn delete(self, gl: &Gl) {
+        gl.DeleteFramebuffers(1, &self.draw_framebuffer);
+        if let Some(resolve_framebuffer) = &self.resolve_framebuffer {
+            gl.DeleteFramebuffers(1, resolve_framebuffer);
+        }
+
+        gl.DeleteRenderbuffers(
+            self.renderbuffers.len() as GLsizei,
+            self.renderbuffers.as_ptr(),
+        );
+    }
+
+    /// Bind this framebuffer as the target for all following draw calls.
+    pub unsafe fn bind_for_drawing(&self, gl: &Gl) {
+        gl.BindFramebuffer(gl::FRAMEBUFFER, self.draw_framebuffer);
+    }
+
+    /// Resolve any multisampled data and bind the result so it can be read with glReadPixels.
+    pub unsafe fn bind_for_reading(&self, gl: &Gl) {
+        let resolve_framebuffer = match self.resolve_framebuffer {
+            Some(x) => x,
+            None => {
+                gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.draw_framebuffer);
+                return;
+            }
+        };
+
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.draw_framebuffer);
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, resolve_framebuffer);
+        gl.BlitFramebuffer(
+            0,
+            0,
+            self.width as GLint,
+            self.height as GLint,
+            0,
+            0,
+            self.width as GLint,
+            self.height as GLint,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, resolve_framebuffer);
+    }
+}
+
+unsafe fn create_renderbuffer(
+    gl: &Gl,
+    format: GLuint,
+    width: u32,
+    height: u32,
+    samples: u32,
+) -> GLuint {
+    let mut renderbuffer = 0;
+    gl.GenRenderbuffers(1, &mut renderbuffer);
+    gl.BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+
+    if samples > 1 {
+        gl.RenderbufferStorageMultisample(
+            gl::RENDERBUFFER,
+            samples as GLsizei,
+            format,
+            width as GLsizei,
+            height as GLsizei,
+        );
+    } else {
+        gl.RenderbufferStorage(
+            gl::RENDERBUFFER,
+            format,
+            width as GLsizei,
+            height as GLsizei,
+        );
+    }
+
+    renderbuffer
+}
+
+unsafe fn create_framebuffer(
+    gl: &Gl,
+    width: u32,
+    height: u32,
+    samples: u32,
+    renderbuffers: &mut Vec<GLuint>,
+) -> GLuint {
+    let mut framebuffer = 0;
+    gl.GenFramebuffers(1, &mut framebuffer);
+    gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+
+    let color = create_renderbuffer(gl, gl::RGBA8, width, height, samples);
+    gl.FramebufferRenderbuffer(
+        gl::FRAMEBUFFER,
+        gl::COLOR_ATTACHMENT0,
+        gl::RENDERBUFFER,
+        color,
+    );
+
+    let depth = create_renderbuffer(gl, gl::DEPTH_COMPONENT24, width, height, samples);
+    gl.FramebufferRenderbuffer(
+        gl::FRAMEBUFFER,
+        gl::DEPTH_ATTACHMENT,
+        gl::RENDERBUFFER,
+        depth,
+    );
+
+    renderbuffers.extend([color, depth]);
+    framebuffer
+}
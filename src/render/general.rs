@@ -1,7 +1,11 @@
 use crate::c_str;
+use crate::error::Result;
 use crate::render::gl::types::{GLint, GLuint};
-use crate::render::shader::{build_program, ShaderError};
-use crate::render::{check_for_errors, gl, Gl};
+use crate::render::material::Material;
+use crate::render::shader::build_program;
+use crate::render::shadow::{LightSpace, ShadowQuality, SHADOW_STRENGTH};
+use crate::render::util::check_for_errors;
+use crate::render::{gl, Gl};
 use nalgebra_glm::{Mat4, Vec3};
 
 pub struct GeneralProgram {
@@ -10,7 +14,7 @@ pub struct GeneralProgram {
 }
 
 impl GeneralProgram {
-    pub unsafe fn build(gl: &Gl) -> Result<Self, ShaderError> {
+    pub unsafe fn build(gl: &Gl) -> Result<Self> {
         let vert = c_str!(include_str!("../vert.glsl"));
         let frag = c_str!(include_str!("../frag.glsl"));
 
@@ -19,7 +23,7 @@ impl GeneralProgram {
 
         Ok(GeneralProgram {
             program,
-            uniforms: GeneralProgramUniforms::from_program(gl, program),
+            uniforms: GeneralProgramUniforms::from_program(gl, program)?,
         })
     }
 }
@@ -31,10 +35,22 @@ pub struct GeneralProgramUniforms {
     camera: GLint,
     light_direction: GLint,
     material_color: GLint,
+    material_roughness: GLint,
+    material_emission: GLint,
+    material_opacity: GLint,
+    light_space: GLint,
+    shadows_enabled: GLint,
+    shadow_map: GLint,
+    shadow_filter_radius: GLint,
+    shadow_bias: GLint,
+    shadow_strength: GLint,
+    ambient_occlusion_enabled: GLint,
+    ambient_occlusion: GLint,
+    ambient_occlusion_strength: GLint,
 }
 
 impl GeneralProgramUniforms {
-    unsafe fn from_program(gl: &Gl, program: GLuint) -> Self {
+    unsafe fn from_program(gl: &Gl, program: GLuint) -> Result<Self> {
         let uniforms = GeneralProgramUniforms {
             model: gl.GetUniformLocation(program, c_str!("model").as_ptr()),
             view: gl.GetUniformLocation(program, c_str!("view").as_ptr()),
@@ -42,10 +58,26 @@ impl GeneralProgramUniforms {
             camera: gl.GetUniformLocation(program, c_str!("camera").as_ptr()),
             light_direction: gl.GetUniformLocation(program, c_str!("lightDirection").as_ptr()),
             material_color: gl.GetUniformLocation(program, c_str!("materialColor").as_ptr()),
+            material_roughness: gl
+                .GetUniformLocation(program, c_str!("materialRoughness").as_ptr()),
+            material_emission: gl.GetUniformLocation(program, c_str!("materialEmission").as_ptr()),
+            material_opacity: gl.GetUniformLocation(program, c_str!("materialOpacity").as_ptr()),
+            light_space: gl.GetUniformLocation(program, c_str!("lightSpace").as_ptr()),
+            shadows_enabled: gl.GetUniformLocation(program, c_str!("shadowsEnabled").as_ptr()),
+            shadow_map: gl.GetUniformLocation(program, c_str!("shadowMap").as_ptr()),
+            shadow_filter_radius: gl
+                .GetUniformLocation(program, c_str!("shadowFilterRadius").as_ptr()),
+            shadow_bias: gl.GetUniformLocation(program, c_str!("shadowBias").as_ptr()),
+            shadow_strength: gl.GetUniformLocation(program, c_str!("shadowStrength").as_ptr()),
+            ambient_occlusion_enabled: gl
+                .GetUniformLocation(program, c_str!("ambientOcclusionEnabled").as_ptr()),
+            ambient_occlusion: gl.GetUniformLocation(program, c_str!("ambientOcclusion").as_ptr()),
+            ambient_occlusion_strength: gl
+                .GetUniformLocation(program, c_str!("ambientOcclusionStrength").as_ptr()),
         };
 
-        check_for_errors(gl);
-        uniforms
+        check_for_errors(gl)?;
+        Ok(uniforms)
     }
 
     pub unsafe fn set_model(&self, gl: &Gl, model: &Mat4) {
@@ -73,7 +105,54 @@ impl GeneralProgramUniforms {
         gl.Uniform3fv(self.light_direction, 1, x.as_ptr() as *const _);
     }
 
-    pub unsafe fn set_material_color(&self, gl: &Gl, x: &Vec3) {
-        gl.Uniform3fv(self.material_color, 1, x.as_ptr() as *const _);
+    pub unsafe fn set_material(&self, gl: &Gl, material: &Material) {
+        gl.Uniform3fv(self.material_color, 1, material.color.as_ptr() as *const _);
+        gl.Uniform1f(self.material_roughness, material.roughness);
+        gl.Uniform1f(self.material_emission, material.emission);
+        gl.Uniform1f(self.material_opacity, material.opacity);
+    }
+
+    /// Enable sampling of the shadow map bound to the given texture unit, or disable shadows when
+    /// no light space is given.
+    pub unsafe fn set_shadows(
+        &self,
+        gl: &Gl,
+        shadows: Option<(&LightSpace, ShadowQuality)>,
+        texture_unit: GLuint,
+    ) {
+        let Some((light_space, quality)) = shadows else {
+            gl.Uniform1i(self.shadows_enabled, gl::FALSE as GLint);
+            return;
+        };
+
+        gl.Uniform1i(self.shadows_enabled, gl::TRUE as GLint);
+        gl.Uniform1i(self.shadow_map, texture_unit as GLint);
+        gl.Uniform1i(self.shadow_filter_radius, quality.filter_radius());
+        gl.Uniform1f(self.shadow_bias, light_space.bias);
+        gl.Uniform1f(self.shadow_strength, SHADOW_STRENGTH);
+        gl.UniformMatrix4fv(
+            self.light_space,
+            1,
+            gl::FALSE,
+            light_space.transform.as_ptr() as *const _,
+        );
+    }
+
+    /// Darken surfaces by the ambient occlusion bound to the given texture unit, or disable ambient
+    /// occlusion when no strength is given.
+    pub unsafe fn set_ambient_occlusion(
+        &self,
+        gl: &Gl,
+        strength: Option<f32>,
+        texture_unit: GLuint,
+    ) {
+        let Some(strength) = strength else {
+            gl.Uniform1i(self.ambient_occlusion_enabled, gl::FALSE as GLint);
+            return;
+        };
+
+        gl.Uniform1i(self.ambient_occlusion_enabled, gl::TRUE as GLint);
+        gl.Uniform1i(self.ambient_occlusion, texture_unit as GLint);
+        gl.Uniform1f(self.ambient_occlusion_strength, strength);
     }
 }
@@ -3,7 +3,9 @@
     clippy::unused_unit,
     clippy::upper_case_acronyms,
     clippy::too_many_arguments,
-    clippy::manual_non_exhaustive
+    clippy::manual_non_exhaustive,
+    clippy::missing_transmute_annotations,
+    clippy::missing_safety_doc
 )]
 
 // Basically equivalent to C's #include.
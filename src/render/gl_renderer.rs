@@ -0,0 +1,473 @@
+use crate::blueprint::Blueprint;
+use crate::error::{Error, Result};
+use crate::render::camera::Camera;
+use crate::render::context::{setup_headless_opengl, setup_opengl, DisplayManager};
+use crate::render::general::GeneralProgram;
+use crate::render::gl::types::{GLint, GLsizei, GLuint};
+use crate::render::material::Material;
+use crate::render::scene::{
+    ground_material, ground_plane, light_direction, place_models, premultiply, sort_for_blending,
+    PlacedModel,
+};
+use crate::render::shadow::{LightSpace, ShadowMap, ShadowProgram, ShadowQuality};
+use crate::render::ssao::{AmbientOcclusionPrograms, AmbientOcclusionTargets};
+use crate::render::util::{check_for_errors, load_vbo, upload_vbo};
+use crate::render::vertex::{vertex_buffer_for_model, Vertex};
+use crate::render::{gl, RenderOptions, Renderer};
+use crate::tweaks::ModelLoader;
+use image::imageops::flip_vertical_in_place;
+use image::RgbaImage;
+use log::info;
+use nalgebra_glm::Mat4;
+use obj::Obj;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Renderer backed by an OpenGL context, either through a hidden window or headless EGL.
+///
+/// The compiled shaders and the vertex buffers of every model drawn are kept in graphics memory,
+/// so reusing a renderer for multiple blueprints avoids repeating that work.
+pub struct GlRenderer {
+    graphics: DisplayManager,
+    program: GeneralProgram,
+    shadow_program: ShadowProgram,
+    ssao_programs: AmbientOcclusionPrograms,
+    vao: GLuint,
+    /// A vertex array without any attributes, used to draw passes covering the entire screen
+    screen_vao: GLuint,
+    ground_plane_vbo: GLuint,
+    model_buffers: HashMap<*const Obj, ModelBuffer>,
+    /// Created on the first render with shadows, and recreated when the shadow quality changes
+    shadow_map: Option<ShadowMap>,
+    /// Created on the first render with ambient occlusion, and recreated when the render size
+    /// changes
+    ssao_targets: Option<AmbientOcclusionTargets>,
+}
+
+/// The texture unit from which the main program samples the shadow map
+const SHADOW_MAP_UNIT: GLuint = 0;
+
+/// The texture unit from which the main program samples the ambient occlusion
+const AMBIENT_OCCLUSION_UNIT: GLuint = 1;
+
+/// A model which has been uploaded to graphics memory
+struct ModelBuffer {
+    // Holding a reference to the model ensures the pointer used as the key is never reused
+    _model: Rc<Obj>,
+    vbo: GLuint,
+    vertex_count: GLsizei,
+}
+
+impl GlRenderer {
+    /// Create a graphics context with a render target of at least the given size.
+    pub fn new(width: u32, height: u32, headless: bool) -> Result<Self> {
+        let graphics = match headless {
+            true => setup_headless_opengl(width, height)?,
+            false => setup_opengl(width, height)?.1,
+        };
+
+        unsafe {
+            let shader_compile_start_time = Instant::now();
+            let program = GeneralProgram::build(&graphics)?;
+            let shadow_program = ShadowProgram::build(&graphics)?;
+            let ssao_programs = AmbientOcclusionPrograms::build(&graphics)?;
+            info!(
+                "Loaded and compiled shaders in {:?}",
+                shader_compile_start_time.elapsed()
+            );
+
+            let mut vao = 0;
+            graphics.GenVertexArrays(1, &mut vao);
+
+            let mut screen_vao = 0;
+            graphics.GenVertexArrays(1, &mut screen_vao);
+
+            let ground_plane_vbo = load_vbo::<Vertex>(&graphics, &[]);
+            check_for_errors(&graphics)?;
+
+            Ok(GlRenderer {
+                graphics,
+                program,
+                shadow_program,
+                ssao_programs,
+                vao,
+                screen_vao,
+                ground_plane_vbo,
+                model_buffers: HashMap::new(),
+                shadow_map: None,
+                ssao_targets: None,
+            })
+        }
+    }
+}
+
+impl Renderer for GlRenderer {
+    fn max_render_size(&self) -> (u32, u32) {
+        self.graphics.max_render_size()
+    }
+
+    fn msaa_samples(&self) -> u32 {
+        self.graphics.msaa_samples()
+    }
+
+    fn color_sample_size(&self) -> u8 {
+        self.graphics.color_sample_size()
+    }
+
+    fn reserve_render_size(&mut self, width: u32, height: u32) -> Result<()> {
+        unsafe { self.graphics.reserve_render_size(width, height) }
+    }
+
+    fn draw(
+        &mut self,
+        blueprint: &Blueprint,
+        model_loader: &mut ModelLoader,
+        options: &RenderOptions,
+        width: u32,
+        height: u32,
+    ) -> Result<RgbaImage> {
+        unsafe { self.perform_render_impl(blueprint, model_loader, options, width, height) }
+    }
+}
+
+impl GlRenderer {
+    unsafe fn perform_render_impl(
+        &mut self,
+        blueprint: &Blueprint,
+        model_loader: &mut ModelLoader,
+        options: &RenderOptions,
+        width: u32,
+        height: u32,
+    ) -> Result<RgbaImage> {
+        let render_start_time = Instant::now();
+
+        let scene = place_models(blueprint, model_loader, options.style)?;
+        let mut models = send_models_to_gpu(&self.graphics, &mut self.model_buffers, &scene.models);
+
+        let camera = Camera::fit_to_aabb(
+            scene.aabb,
+            scene.ground_height,
+            width,
+            height,
+            &options.camera,
+        );
+
+        // The shadow map is drawn first, as it replaces the bound framebuffer and viewport
+        let light_space = match options.shadows {
+            true => Some(self.draw_shadow_map(
+                &models,
+                LightSpace::fit_to_aabb(scene.aabb, scene.ground_height),
+                options.shadow_quality,
+            )?),
+            false => None,
+        };
+
+        if options.ground_plane {
+            upload_vbo(
+                &self.graphics,
+                self.ground_plane_vbo,
+                &ground_plane(camera.projection * camera.view, scene.ground_height),
+            );
+            models.push(ModelGraphics {
+                vbo: self.ground_plane_vbo,
+                vertex_count: 6,
+                model_uniform: Mat4::identity(),
+                material: ground_material(options.style),
+            });
+        }
+
+        if options.ssao {
+            self.draw_ambient_occlusion(&models, &camera, width, height, options.ssao_radius)?;
+        }
+
+        self.graphics.bind_for_drawing();
+
+        let graphics = &self.graphics;
+        let program = &self.program;
+
+        // Check that we actually have a buffer setup correctly
+        let framebuffer_status = graphics.CheckFramebufferStatus(gl::FRAMEBUFFER);
+        if framebuffer_status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(Error::IncompleteFramebuffer(framebuffer_status));
+        }
+
+        info!("Beginning render of size ({}, {})", width, height);
+        graphics.Viewport(0, 0, width as GLsizei, height as GLsizei);
+        check_for_errors(graphics)?;
+
+        let background = premultiply(options.background);
+        graphics.ClearColor(background.x, background.y, background.z, background.w);
+        graphics.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+        graphics.Enable(gl::DEPTH_TEST);
+        graphics.DepthFunc(gl::LESS);
+
+        graphics.UseProgram(program.program);
+        graphics.BindVertexArray(self.vao);
+
+        program.uniforms.set_view(graphics, &camera.view);
+        program
+            .uniforms
+            .set_projection(graphics, &camera.projection);
+        program.uniforms.set_camera(graphics, &camera.position);
+        program
+            .uniforms
+            .set_light_direction(graphics, &light_direction());
+
+        if let (Some(shadow_map), true) = (&self.shadow_map, options.shadows) {
+            shadow_map.bind_texture(graphics, SHADOW_MAP_UNIT);
+        }
+        program.uniforms.set_shadows(
+            graphics,
+            light_space
+                .as_ref()
+                .map(|light_space| (light_space, options.shadow_quality)),
+            SHADOW_MAP_UNIT,
+        );
+
+        if let (Some(ssao_targets), true) = (&self.ssao_targets, options.ssao) {
+            ssao_targets.bind_texture(graphics, AMBIENT_OCCLUSION_UNIT);
+        }
+        program.uniforms.set_ambient_occlusion(
+            graphics,
+            options.ssao.then_some(options.ssao_strength),
+            AMBIENT_OCCLUSION_UNIT,
+        );
+
+        // Translucent meshes are blended over everything drawn before them, so they are drawn last.
+        // They do not write to the depth buffer, so they never hide each other.
+        sort_for_blending(&mut models, camera.position, |model| {
+            (&model.model_uniform, &model.material)
+        });
+        graphics.BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+
+        for model in &models {
+            graphics.BindBuffer(gl::ARRAY_BUFFER, model.vbo);
+            graphics.BindVertexArray(self.vao);
+            Vertex::configure_vao(graphics);
+
+            program.uniforms.set_model(graphics, &model.model_uniform);
+            program.uniforms.set_material(graphics, &model.material);
+
+            if model.material.is_translucent() {
+                graphics.Enable(gl::BLEND);
+                graphics.DepthMask(gl::FALSE);
+            }
+
+            graphics.DrawArrays(gl::TRIANGLES, 0, model.vertex_count);
+        }
+        graphics.Disable(gl::BLEND);
+        graphics.DepthMask(gl::TRUE);
+
+        let mut buffer = vec![0u8; (width * height * 4) as usize];
+
+        info!("Waiting for completion of graphics render queue");
+        graphics.Finish();
+        info!(
+            "Render completed. Total elapsed time to perform render: {:?}",
+            render_start_time.elapsed()
+        );
+
+        info!("Performing call to glReadPixels to fetch image from graphics memory");
+        graphics.bind_for_reading();
+
+        let read_pixels_start_time = Instant::now();
+        graphics.ReadPixels(
+            0,
+            0,
+            width as GLsizei,
+            height as GLsizei,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            buffer.as_mut_ptr() as *mut _,
+        );
+        info!(
+            "Completed call glReadPixels in {:?}",
+            read_pixels_start_time.elapsed()
+        );
+
+        check_for_errors(graphics)?;
+        match RgbaImage::from_raw(width, height, buffer) {
+            Some(mut img) => {
+                flip_vertical_in_place(&mut img);
+                Ok(img)
+            }
+            None => unreachable!("Buffer was created with the correct size"),
+        }
+    }
+}
+
+impl GlRenderer {
+    /// Draw the depth of every opaque mesh, as seen from the light, into the shadow map.
+    /// Translucent meshes let light through, so they do not cast shadows.
+    unsafe fn draw_shadow_map(
+        &mut self,
+        models: &[ModelGraphics],
+        light_space: LightSpace,
+        quality: ShadowQuality,
+    ) -> Result<LightSpace> {
+        let graphics = &self.graphics;
+
+        let mut max_texture_size: GLint = 0;
+        graphics.GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_texture_size);
+        let size = quality.map_size().min(max_texture_size.max(1) as u32);
+
+        let shadow_map = match self.shadow_map.take() {
+            Some(shadow_map) if shadow_map.size == size => shadow_map,
+            previous => {
+                if let Some(previous) = previous {
+                    previous.delete(graphics);
+                }
+                ShadowMap::new(graphics, size)?
+            }
+        };
+        let shadow_map = self.shadow_map.insert(shadow_map);
+
+        let shadow_start_time = Instant::now();
+        shadow_map.bind_for_drawing(graphics);
+
+        graphics.Enable(gl::DEPTH_TEST);
+        graphics.DepthFunc(gl::LESS);
+
+        // Push depths away from the light in proportion to their slope, so surfaces facing away
+        // from the light do not shadow themselves
+        graphics.Enable(gl::POLYGON_OFFSET_FILL);
+        graphics.PolygonOffset(2.0, 4.0);
+
+        let program = &self.shadow_program;
+        graphics.UseProgram(program.program);
+        graphics.BindVertexArray(self.vao);
+        program.set_light_space(graphics, &light_space.transform);
+
+        for model in models
+            .iter()
+            .filter(|model| !model.material.is_translucent())
+        {
+            graphics.BindBuffer(gl::ARRAY_BUFFER, model.vbo);
+            Vertex::configure_vao(graphics);
+
+            program.set_model(graphics, &model.model_uniform);
+            graphics.DrawArrays(gl::TRIANGLES, 0, model.vertex_count);
+        }
+        graphics.Disable(gl::POLYGON_OFFSET_FILL);
+
+        check_for_errors(graphics)?;
+        info!("Drew shadow map in {:?}", shadow_start_time.elapsed());
+        Ok(light_space)
+    }
+
+    /// Draw the normal and depth of every opaque mesh into the G-buffer, then find and blur the
+    /// ambient occlusion of each pixel from it. Translucent meshes are left out, so they neither
+    /// occlude nor are occluded.
+    unsafe fn draw_ambient_occlusion(
+        &mut self,
+        models: &[ModelGraphics],
+        camera: &Camera,
+        width: u32,
+        height: u32,
+        radius: f32,
+    ) -> Result<()> {
+        let graphics = &self.graphics;
+
+        let targets = match self.ssao_targets.take() {
+            Some(targets) if targets.width == width && targets.height == height => targets,
+            previous => {
+                if let Some(previous) = previous {
+                    previous.delete(graphics);
+                }
+                AmbientOcclusionTargets::new(graphics, width, height)?
+            }
+        };
+        let targets = self.ssao_targets.insert(targets);
+
+        let ssao_start_time = Instant::now();
+        targets.bind_g_buffer(graphics);
+
+        graphics.Enable(gl::DEPTH_TEST);
+        graphics.DepthFunc(gl::LESS);
+
+        let programs = &self.ssao_programs;
+        programs.use_g_buffer(graphics, &camera.view, &camera.projection);
+        graphics.BindVertexArray(self.vao);
+
+        for model in models
+            .iter()
+            .filter(|model| !model.material.is_translucent())
+        {
+            graphics.BindBuffer(gl::ARRAY_BUFFER, model.vbo);
+            Vertex::configure_vao(graphics);
+
+            programs.set_g_buffer_model(graphics, &model.model_uniform);
+            graphics.DrawArrays(gl::TRIANGLES, 0, model.vertex_count);
+        }
+
+        graphics.BindVertexArray(self.screen_vao);
+        programs.draw_occlusion(graphics, targets, &camera.projection, radius);
+
+        // The blur pass leaves its own framebuffer bound, which must not receive the scene
+        graphics.bind_for_drawing();
+
+        check_for_errors(graphics)?;
+        info!("Drew ambient occlusion in {:?}", ssao_start_time.elapsed());
+        Ok(())
+    }
+}
+
+/// A single draw of a mesh
+struct ModelGraphics {
+    vbo: GLuint,
+    vertex_count: GLsizei,
+    model_uniform: Mat4,
+    material: Material,
+}
+
+unsafe fn send_models_to_gpu(
+    gl: &DisplayManager,
+    model_buffers: &mut HashMap<*const Obj, ModelBuffer>,
+    placed_models: &[PlacedModel],
+) -> Vec<ModelGraphics> {
+    let mut models = Vec::with_capacity(placed_models.len());
+
+    let mut model_vertex_buffer = Vec::new();
+
+    let mut vertex_build_time = Duration::default();
+    let mut gpu_upload_time = Duration::default();
+
+    for PlacedModel {
+        model,
+        transform,
+        material,
+    } in placed_models
+    {
+        let buffer = model_buffers.entry(Rc::as_ptr(model)).or_insert_with(|| {
+            let vertex_build_start_time = Instant::now();
+            model_vertex_buffer.clear();
+            vertex_buffer_for_model(&mut model_vertex_buffer, model);
+            vertex_build_time += vertex_build_start_time.elapsed();
+
+            let vbo_creation_start_time = Instant::now();
+            let vbo = load_vbo(gl, &model_vertex_buffer);
+            gpu_upload_time += vbo_creation_start_time.elapsed();
+
+            ModelBuffer {
+                _model: model.clone(),
+                vbo,
+                vertex_count: model_vertex_buffer.len() as GLsizei,
+            }
+        });
+
+        models.push(ModelGraphics {
+            vbo: buffer.vbo,
+            vertex_count: buffer.vertex_count,
+            model_uniform: *transform,
+            material: *material,
+        });
+    }
+
+    info!("Sent model data to graphics memory:");
+    info!("Vertex list build time: {:?}", vertex_build_time);
+    info!("GL buffer upload time: {:?}", gpu_upload_time);
+
+    models
+}
@@ -0,0 +1,90 @@
+use crate::error::Result;
+use crate::render::scene::Aabb;
+use crate::tweaks::ModelLoader;
+use nalgebra_glm::{rotate_y, scale, translate, translation, Mat4, Vec3};
+use num_traits::FloatConst;
+use obj::Obj;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Glyph models are named with this prefix followed by an uppercase letter
+const GLYPH_MODEL_PREFIX: &str = "LabelSupport_";
+
+/// The width of the label plate which may be covered by text
+const TEXT_WIDTH: f32 = 4.6;
+
+/// The height of the recessed surface of the label plate that the glyphs rest on
+const TEXT_ELEVATION: f32 = 0.26;
+
+/// The gap left between neighboring glyphs
+const GLYPH_SPACING: f32 = 0.08;
+
+/// The width of a space, also used for any character without a glyph model
+const SPACE_WIDTH: f32 = 0.3;
+
+/// A glyph model positioned on a label
+pub struct Glyph {
+    pub model: Rc<Obj>,
+    /// The transform from the space of the glyph model into the space of the label
+    pub transform: Mat4,
+}
+
+/// Lay out the glyphs for a line of label text. The text is centered on the label plate and
+/// shrunk when it would not otherwise fit. Only the letters A to Z have glyph models, so letters
+/// are drawn in uppercase and any other characters are left as spaces.
+pub fn layout_label(
+    text: &str,
+    model_loader: &mut ModelLoader,
+    model_bounds: &mut HashMap<*const Obj, Aabb>,
+) -> Result<Vec<Glyph>> {
+    let mut glyphs = Vec::new();
+    let mut cursor = 0.0;
+
+    for character in text.chars().map(|c| c.to_ascii_uppercase()) {
+        let model = match character {
+            'A'..='Z' => {
+                let name = format!("{}{}", GLYPH_MODEL_PREFIX, character);
+                model_loader
+                    .load_model(&name)?
+                    .first()
+                    .map(|model| model.model.clone())
+            }
+            _ => None,
+        };
+
+        match model {
+            Some(model) => {
+                let bounds = *model_bounds
+                    .entry(Rc::as_ptr(&model))
+                    .or_insert_with(|| Aabb::for_model(&model));
+
+                glyphs.push((model, bounds, cursor));
+                cursor += bounds.max.x - bounds.min.x + GLYPH_SPACING;
+            }
+            None => cursor += SPACE_WIDTH + GLYPH_SPACING,
+        }
+    }
+
+    let text_width = cursor - GLYPH_SPACING;
+    let text_scale = f32::min(1.0, TEXT_WIDTH / text_width);
+
+    Ok(glyphs
+        .into_iter()
+        .map(|(model, bounds, start)| {
+            let width = bounds.max.x - bounds.min.x;
+            let center = (start + width / 2.0 - text_width / 2.0) * text_scale;
+
+            // Placed as they are, the glyphs appear upside down to the default camera, so the
+            // text is turned around to be readable from the camera instead
+            let transform = translation(&Vec3::new(-center, TEXT_ELEVATION, 0.0));
+            let transform = rotate_y(&transform, f32::PI());
+            let transform = scale(&transform, &Vec3::new(text_scale, 1.0, text_scale));
+            let transform = translate(
+                &transform,
+                &Vec3::new(-(bounds.min.x + bounds.max.x) / 2.0, 0.0, 0.0),
+            );
+
+            Glyph { model, transform }
+        })
+        .collect())
+}
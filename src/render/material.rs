@@ -0,0 +1,161 @@
+use clap::ValueEnum;
+use nalgebra_glm::Vec3;
+use serde::Deserialize;
+
+/// The overall look of a render
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderStyle {
+    /// Every building is drawn in a single color, in the style of an in-game blueprint
+    #[default]
+    Blueprint,
+    /// Each part of a building is drawn with its own material, such as metal, glass or fluid
+    Realistic,
+}
+
+/// The surface properties used when shading a mesh. This mirrors the material uniforms of
+/// `frag.glsl`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Material {
+    pub color: Vec3,
+    /// How rough the surface is, from 0 (polished) to 1 (matte). Smoother surfaces have tighter
+    /// shading and a white highlight.
+    pub roughness: f32,
+    /// The fraction of the color which is emitted regardless of lighting
+    pub emission: f32,
+    /// How much of the surface behind is hidden, from 0 (invisible) to 1 (opaque)
+    pub opacity: f32,
+}
+
+/// The material of building parts which do not match any other material
+const DEFAULT_MATERIAL: Material = Material::new(Vec3::new(0.78, 0.8, 0.83), 0.8);
+
+const EMISSIVE: Material = Material::new(Vec3::new(0.45, 0.85, 1.0), 1.0).emissive(1.0);
+const GLASS: Material = Material::new(Vec3::new(0.7, 0.85, 0.95), 0.05).translucent(0.35);
+const FLUID: Material = Material::new(Vec3::new(0.15, 0.45, 0.85), 0.2).translucent(0.85);
+const BLUE_METAL: Material = Material::new(Vec3::new(0.12, 0.3, 0.58), 0.45);
+const DARK_METAL: Material = Material::new(Vec3::new(0.1, 0.11, 0.13), 0.55);
+const BRIGHT_METAL: Material = Material::new(Vec3::new(0.72, 0.74, 0.78), 0.35);
+const METAL: Material = Material::new(Vec3::new(0.45, 0.47, 0.5), 0.45);
+const BELT: Material = Material::new(Vec3::new(0.16, 0.16, 0.17), 0.85);
+const STAND: Material = Material::new(Vec3::new(0.22, 0.23, 0.26), 0.6);
+const FOUNDATION: Material = Material::new(Vec3::new(0.28, 0.3, 0.33), 0.9);
+const STATION_FRAME: Material = Material::new(Vec3::new(0.18, 0.19, 0.22), 0.7);
+
+/// Materials of the parts of buildings, matched by the end of a mesh or material name ignoring
+/// case (see [`part_name`]). Checked in order, so longer names must come before any name they end
+/// with.
+const PART_MATERIALS: [(&str, Material); 11] = [
+    ("emission", EMISSIVE),
+    ("emissive", EMISSIVE),
+    ("glass", GLASS),
+    ("glas", GLASS),
+    ("fluid", FLUID),
+    ("bluemetal", BLUE_METAL),
+    ("darkmetal", DARK_METAL),
+    ("brightmetal", BRIGHT_METAL),
+    ("metal", METAL),
+    ("borders", METAL),
+    ("belt", BELT),
+];
+
+/// Materials of whole models, matched by part of the model name ignoring case
+const MODEL_MATERIALS: [(&str, Material); 6] = [
+    ("stand", STAND),
+    ("tile_", FOUNDATION),
+    ("wall_", FOUNDATION),
+    ("corner_", FOUNDATION),
+    ("notch_", FOUNDATION),
+    ("stationframe", STATION_FRAME),
+];
+
+/// Get the lowercase name of a part with any `Mesh` suffix or trailing side removed, such as
+/// `mixermiddlefluid` for `MixerMiddleFluidL`. The material of a part is named at the end, so
+/// `SandboxIFluidProducer` is not a fluid.
+fn part_name(name: &str) -> String {
+    let name = name.strip_suffix("Mesh").unwrap_or(name);
+
+    let mut chars = name.chars().rev();
+    let name = match (chars.next(), chars.next()) {
+        (Some(side), Some(before)) if side.is_ascii_uppercase() && before.is_ascii_lowercase() => {
+            &name[..name.len() - 1]
+        }
+        _ => name,
+    };
+
+    name.to_ascii_lowercase()
+}
+
+/// Check whether a mesh or material name refers to the fluid within a building.
+pub fn is_fluid(name: &str) -> bool {
+    part_name(name).ends_with("fluid")
+}
+
+/// Get the color of a fluid from its color code, such as `r` for red.
+pub fn fluid_color(code: char) -> Option<Vec3> {
+    Some(match code {
+        'r' => Vec3::new(0.9, 0.16, 0.16),
+        'g' => Vec3::new(0.25, 0.85, 0.25),
+        'b' => Vec3::new(0.18, 0.35, 0.95),
+        'c' => Vec3::new(0.15, 0.85, 0.9),
+        'm' => Vec3::new(0.85, 0.2, 0.85),
+        'y' => Vec3::new(0.95, 0.85, 0.15),
+        'w' => Vec3::new(0.95, 0.95, 0.95),
+        'k' => Vec3::new(0.06, 0.06, 0.06),
+        'u' => Vec3::new(0.55, 0.55, 0.55),
+        _ => return None,
+    })
+}
+
+impl Material {
+    const fn new(color: Vec3, roughness: f32) -> Self {
+        Material {
+            color,
+            roughness,
+            emission: 0.0,
+            opacity: 1.0,
+        }
+    }
+
+    const fn emissive(self, emission: f32) -> Self {
+        Material { emission, ..self }
+    }
+
+    const fn translucent(self, opacity: f32) -> Self {
+        Material { opacity, ..self }
+    }
+
+    /// A matte, opaque material of a single color. This is used for every mesh in the blueprint
+    /// style.
+    pub const fn flat(color: Vec3) -> Self {
+        Material::new(color, 1.0)
+    }
+
+    /// Get the material matching a mesh or material name, such as `glass` or `PipeForwardGlas`.
+    /// Names which do not match any material are given a plain painted finish.
+    pub fn for_name(name: &str) -> Self {
+        let part = part_name(name);
+        if let Some((_, material)) = PART_MATERIALS
+            .into_iter()
+            .find(|(pattern, _)| part.ends_with(pattern))
+        {
+            return material;
+        }
+
+        let name = name.to_ascii_lowercase();
+        MODEL_MATERIALS
+            .into_iter()
+            .find(|(pattern, _)| name.contains(pattern))
+            .map_or(DEFAULT_MATERIAL, |(_, material)| material)
+    }
+
+    /// Replace the color of the material, keeping how the surface is shaded.
+    pub fn with_color(self, color: Vec3) -> Self {
+        Material { color, ..self }
+    }
+
+    /// Whether the material must be blended with whatever is behind it.
+    pub fn is_translucent(&self) -> bool {
+        self.opacity < 1.0
+    }
+}
@@ -1,504 +1,342 @@
+mod camera;
 pub mod context;
+mod foundation;
+mod framebuffer;
 mod general;
 pub mod gl;
+mod gl_renderer;
+mod label;
+mod material;
+mod scene;
 mod shader;
+mod shadow;
+mod software;
+mod ssao;
+mod stand;
 mod util;
 mod vertex;
 
-use crate::blueprint::BlueprintEntry;
-use crate::render::context::DisplayManager;
-use crate::render::general::GeneralProgram;
-use crate::render::gl::types::{GLsizei, GLuint};
-use crate::render::util::{check_for_errors, load_vbo};
-use crate::render::vertex::{vertex_buffer_for_model, Vertex};
-use crate::tweaks::{Model, ModelLoader};
-use crate::ARGS;
-pub use context::setup_opengl;
+use crate::blueprint::Blueprint;
+use crate::error::{Error, Result};
+use crate::tweaks::ModelLoader;
+pub use camera::{
+    parse_point, CameraOptions, CameraOverrides, Projection, ViewPreset, DEFAULT_FOV,
+};
 pub use gl::Gl;
-use glutin::config::{ColorBufferType, GlConfig};
-use image::imageops::{flip_vertical_in_place, resize};
-use image::{ImageFormat, RgbImage};
+pub use gl_renderer::GlRenderer;
+use image::imageops::{resize, FilterType};
+use image::{Rgba, RgbaImage};
 use log::{info, warn};
-use nalgebra_glm::{
-    look_at, perspective, rotate_y, rotation, scale, translate, translation, Mat4, Vec3, Vec4,
-};
-use num_traits::FloatConst;
-use obj::Obj;
-use std::collections::HashMap;
-use std::io::{stdout, Cursor, Write};
-use std::rc::Rc;
-use std::time::{Duration, Instant};
-
-pub fn perform_render(
-    entries: &[BlueprintEntry],
-    model_loader: &mut ModelLoader,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut ssaa = ARGS.ssaa.max(1);
-
-    if ssaa > 16 {
-        warn!("SSAA values over 16 will not have a noticeable effect on the image quality");
-        ssaa = 16;
-    }
+pub use material::{Material, RenderStyle};
+use nalgebra_glm::Vec4;
+pub use scene::parse_color;
+use scene::CLEAR_COLOR;
+use serde::Deserialize;
+pub use shader::ShaderError;
+pub use shadow::ShadowQuality;
+pub use software::SoftwareRenderer;
+use std::time::Instant;
+pub use util::GlError;
+
+/// The largest width or height, including SSAA, of a render requested by a render job
+pub const MAX_RENDER_SIZE: u32 = 16384;
+
+/// Settings controlling the size and quality of a render
+#[derive(Copy, Clone, Debug)]
+pub struct RenderOptions {
+    /// The width of the output image
+    pub width: u32,
+    /// The height of the output image
+    pub height: u32,
+    /// The factor by which the render size is increased before being resampled to the output size.
+    /// Values over 16 will not increase the output quality.
+    pub ssaa: u32,
+    /// The filter used when resampling a super sampled render to the output size
+    pub ssaa_sampler: FilterType,
+    /// The placement and projection of the camera
+    pub camera: CameraOptions,
+    /// The color (including alpha) of the image wherever nothing is drawn
+    pub background: Vec4,
+    /// Draw a ground plane beneath the blueprint. This covers the background in most views.
+    pub ground_plane: bool,
+    /// Whether buildings are drawn in a single color or with their materials
+    pub style: RenderStyle,
+    /// Cast shadows from the light onto the ground and other buildings
+    pub shadows: bool,
+    /// The resolution and filtering of shadows, if enabled
+    pub shadow_quality: ShadowQuality,
+    /// Darken the creases and corners between buildings with screen space ambient occlusion. This
+    /// is only supported by the OpenGL renderer.
+    pub ssao: bool,
+    /// The fraction of light blocked within fully occluded creases
+    pub ssao_strength: f32,
+    /// The distance (in tiles) around each point which is searched for occluding surfaces
+    pub ssao_radius: f32,
+}
 
-    let (_, graphics) = setup_opengl(ARGS.width * ssaa, ARGS.height * ssaa);
-
-    let color_sample_size = match graphics.gl_config.color_buffer_type() {
-        Some(ColorBufferType::Rgb {
-            r_size,
-            g_size,
-            b_size,
-        }) => r_size.max(g_size).max(b_size),
-        Some(ColorBufferType::Luminance(size)) => size,
-        None => 8,
-    };
-
-    let color_buffer_resolution = 1u32 << color_sample_size;
-    let msaa_samples = graphics.gl_config.num_samples().max(1);
-    let max_useful_ssaa = (color_buffer_resolution as f64 / msaa_samples as f64)
-        .sqrt()
-        .ceil() as u32;
-
-    // We have already created a window with the larger size, but we can still choose not to use
-    // the entirety of the window for rendering with glViewport.
-    if ssaa > max_useful_ssaa {
-        warn!("The current SSAA setting combined with the system MSAA results in more samples being performed than the resolution of the color buffer. Reducing SSAA from {} to {}.", ssaa, max_useful_ssaa);
-        ssaa = max_useful_ssaa;
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            width: 1980,
+            height: 1080,
+            ssaa: 1,
+            ssaa_sampler: FilterType::Triangle,
+            camera: CameraOptions::default(),
+            background: CLEAR_COLOR,
+            ground_plane: true,
+            style: RenderStyle::Blueprint,
+            shadows: false,
+            shadow_quality: ShadowQuality::Medium,
+            ssao: false,
+            ssao_strength: 1.0,
+            ssao_radius: 0.5,
+        }
     }
+}
 
-    if ssaa != 1 {
-        info!(
-            "Using SSAA to increase render samples by factor of {}",
-            ssaa
-        );
+impl RenderOptions {
+    /// The SSAA factor which will be requested from the renderer.
+    pub fn clamped_ssaa(&self) -> u32 {
+        self.ssaa.clamp(1, 16)
     }
 
-    let mut render_width = ARGS.width * ssaa;
-    let mut render_height = ARGS.height * ssaa;
-
-    let window_size = graphics.window.inner_size();
-    if render_width > window_size.width || render_height > window_size.height {
-        warn!(
-            "Window provided by system is not large enough to render with the output size and SSAA"
-        );
-
-        (render_width, render_height) = clamp_with_aspect_ratio(
-            render_width,
-            render_height,
-            window_size.width,
-            window_size.height,
-        );
+    /// The size of the render before it is resampled to the output size. Sizes too large to be
+    /// represented are saturated, so they are later clamped to the largest supported render.
+    pub fn supersampled_size(&self) -> (u32, u32) {
+        let ssaa = self.clamped_ssaa();
+        (
+            self.width.saturating_mul(ssaa),
+            self.height.saturating_mul(ssaa),
+        )
     }
 
-    let mut img = unsafe {
-        perform_render_impl(graphics, entries, model_loader, render_width, render_height)
-    };
-
-    if img.width() != ARGS.width || img.height() != ARGS.height {
-        // TODO: Add CLI argument for resample type
-        let resample_filter = ARGS.ssaa_sampler.0;
-
-        info!(
-            "Resampling image from render size ({}, {}) to desired size ({}, {}) using {:?} filter",
-            img.width(),
-            img.height(),
-            ARGS.width,
-            ARGS.height,
-            resample_filter
-        );
-
-        let resize_start_time = Instant::now();
-        img = resize(&img, ARGS.width, ARGS.height, resample_filter);
-        info!(
-            "Finished image resampling in {:?}",
-            resize_start_time.elapsed()
-        );
-    }
-
-    match ARGS.out_file.as_ref() {
-        Some(path) => {
-            info!("Saving result as {}", path.display());
-            img.save(path)?;
+    /// Check that the output image is not empty and that the render, including SSAA, is no larger
+    /// than `max_size` on either side.
+    pub fn check_size(&self, max_size: u32) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidOption(
+                "Image size must be non-zero".to_string(),
+            ));
         }
-        None => {
-            info!("Writing result to stdout");
-            let mut buffer = Vec::with_capacity((img.width() * img.height() * 3) as usize);
-            img.write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)?;
-
-            let mut stdout = stdout().lock();
-            stdout.write_all(&buffer)?;
-            stdout.flush()?;
+        if self.ssaa == 0 {
+            return Err(Error::InvalidOption("SSAA must be at least 1".to_string()));
         }
-    }
 
-    Ok(())
-}
-
-fn clamp_with_aspect_ratio(
-    src_width: u32,
-    src_height: u32,
-    dst_width: u32,
-    dst_height: u32,
-) -> (u32, u32) {
-    if src_width <= dst_width && src_height <= dst_height {
-        return (src_width, src_height);
-    }
-
-    let width = src_width * dst_height;
-    let height = src_height * dst_width;
+        let ssaa = self.clamped_ssaa();
+        let too_large =
+            |size: u32| !matches!(size.checked_mul(ssaa), Some(size) if size <= max_size);
+        if too_large(self.width) || too_large(self.height) {
+            return Err(Error::InvalidOption(format!(
+                "Render size ({}, {}) with SSAA {} is larger than the limit of {} on either side",
+                self.width, self.height, ssaa, max_size
+            )));
+        }
 
-    if width > height {
-        (dst_width, height / src_width)
-    } else {
-        (width / src_height, dst_height)
+        Ok(())
     }
 }
 
-unsafe fn perform_render_impl(
-    graphics: DisplayManager,
-    entries: &[BlueprintEntry],
-    model_loader: &mut ModelLoader,
-    width: u32,
-    height: u32,
-) -> RgbImage {
-    let render_start_time = Instant::now();
-
-    // Check that we actually have a buffer setup correctly
-    if graphics.CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
-        panic!("Failed to setup framebuffer!");
-    }
-
-    info!("Beginning render of size ({}, {})", width, height);
-    graphics.Viewport(0, 0, width as GLsizei, height as GLsizei);
-    check_for_errors(&graphics);
-
-    let shader_compile_start_time = Instant::now();
-    let program = unsafe { GeneralProgram::build(&graphics).unwrap() };
-    info!(
-        "Loaded and compiled shaders in {:?}",
-        shader_compile_start_time.elapsed()
-    );
-
-    graphics.ClearColor(0.1, 0.1, 0.1, 1.0);
-    graphics.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-
-    graphics.Enable(gl::DEPTH_TEST);
-    graphics.DepthFunc(gl::LESS);
+/// Changes to the default render options requested by a single render job. Every option which is
+/// not given keeps its default value.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RenderOverrides {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub ssaa: Option<u32>,
+    /// Changes to the default camera settings
+    pub camera: Option<CameraOverrides>,
+    /// The background color, written as `#rrggbb`, `#rrggbbaa` or `transparent`
+    pub background: Option<String>,
+    pub ground_plane: Option<bool>,
+    /// The render style, either `blueprint` or `realistic`
+    pub style: Option<RenderStyle>,
+    pub shadows: Option<bool>,
+    /// The shadow quality, either `low`, `medium` or `high`
+    pub shadow_quality: Option<ShadowQuality>,
+    pub ssao: Option<bool>,
+    pub ssao_strength: Option<f32>,
+    pub ssao_radius: Option<f32>,
+}
 
-    graphics.UseProgram(program.program);
+impl RenderOverrides {
+    /// Get the given options with these overrides applied. Renders which would be empty or larger
+    /// than [`MAX_RENDER_SIZE`] are rejected.
+    pub fn apply(self, defaults: &RenderOptions) -> Result<RenderOptions> {
+        let background = match self.background {
+            Some(color) => parse_color(&color).map_err(Error::InvalidOption)?,
+            None => defaults.background,
+        };
 
-    let mut vao = 0;
-    graphics.GenVertexArrays(1, &mut vao);
-    graphics.BindVertexArray(vao);
+        let options = RenderOptions {
+            width: self.width.unwrap_or(defaults.width),
+            height: self.height.unwrap_or(defaults.height),
+            ssaa: self.ssaa.unwrap_or(defaults.ssaa),
+            camera: self.camera.unwrap_or_default().apply(&defaults.camera),
+            background,
+            ground_plane: self.ground_plane.unwrap_or(defaults.ground_plane),
+            style: self.style.unwrap_or(defaults.style),
+            shadows: self.shadows.unwrap_or(defaults.shadows),
+            shadow_quality: self.shadow_quality.unwrap_or(defaults.shadow_quality),
+            ssao: self.ssao.unwrap_or(defaults.ssao),
+            ssao_strength: self.ssao_strength.unwrap_or(defaults.ssao_strength),
+            ssao_radius: self.ssao_radius.unwrap_or(defaults.ssao_radius),
+            ..*defaults
+        };
 
-    let (mut models, mut aabb) = send_models_to_gpu(&graphics, entries, model_loader);
+        options.check_size(MAX_RENDER_SIZE)?;
+        Ok(options)
+    }
+}
 
-    let mut rotate_model = false;
-    if aabb.max.x - aabb.min.x < aabb.max.z - aabb.min.z {
-        rotate_model = true;
+/// A backend capable of drawing a blueprint to an image.
+pub trait Renderer {
+    /// The largest render size which this renderer is able to produce.
+    fn max_render_size(&self) -> (u32, u32);
 
-        aabb = aabb.apply_transform(&rotation(f32::PI() / 2.0, &Vec3::new(0.0, 1.0, 0.0)))
+    /// The number of samples performed for each pixel of the output.
+    fn msaa_samples(&self) -> u32 {
+        1
     }
 
-    let aspect_ratio = width as f32 / height as f32;
-    let fovy = 0.6;
-
-    // projection
-    let view_vector = Vec3::new(0.0, -1.0, 1.0).normalize();
-
-    // Clip AABB to above ground level
-    aabb.min.y = f32::max(aabb.min.y, 0.0);
-    aabb.max.y = f32::max(aabb.max.y, 0.0);
-
-    let camera_space_aabb = aabb.apply_transform(&look_at(
-        &Vec3::new(0.0, 0.0, 0.0),
-        &view_vector,
-        &Vec3::new(0.0, 1.0, 0.0),
-    ));
-
-    let x_extent = f32::max(camera_space_aabb.min.x.abs(), camera_space_aabb.max.x);
-    let y_extent = f32::max(camera_space_aabb.min.y.abs(), camera_space_aabb.max.y);
-
-    let fovx = aspect_ratio * fovy;
-    let min_x_t = x_extent / (fovx / 2.0).tan();
-    let min_y_t = y_extent / (fovy / 2.0).tan();
-    let t = 1.05 * (f32::max(min_x_t, min_y_t) + camera_space_aabb.min.z.abs());
-
-    let extension = fovy.tan() / fovy.sin();
-    let projection = perspective(
-        aspect_ratio,
-        fovy,
-        0.1,
-        2.0 * extension * (t + camera_space_aabb.max.z),
-    );
-
-    let camera_pos = -t * view_vector;
-    let mut view = look_at(
-        &(-t * view_vector),
-        &Vec3::new(0.0, 0.0, 0.0),
-        &Vec3::new(0.0, 1.0, 0.0),
-    );
-
-    if rotate_model {
-        view *= rotation(f32::PI() / 2.0, &Vec3::new(0.0, 1.0, 0.0));
+    /// The number of bits used to store each color channel of the output.
+    fn color_sample_size(&self) -> u8 {
+        8
     }
 
-    let light_direction = Vec3::new(1.0, -2.0, 1.0).normalize();
-
-    program.uniforms.set_view(&graphics, &view);
-    program.uniforms.set_projection(&graphics, &projection);
-    program.uniforms.set_camera(&graphics, &camera_pos);
-    program
-        .uniforms
-        .set_light_direction(&graphics, &light_direction);
-
-    models.push(ModelGraphics::calculate_ground_plane(
-        &graphics,
-        projection * view,
-    ));
-
-    for model in &models {
-        graphics.BindBuffer(gl::ARRAY_BUFFER, model.vbo);
-        graphics.BindVertexArray(vao);
-        Vertex::configure_vao(&graphics);
-
-        program.uniforms.set_model(&graphics, &model.model_uniform);
-        program
-            .uniforms
-            .set_material_color(&graphics, &model.color_uniform);
-
-        graphics.DrawArrays(gl::TRIANGLES, 0, model.vertex_count);
+    /// Attempt to grow the render target so it can hold a render of the given size. Renderers
+    /// which are unable to grow will instead have the render size clamped to
+    /// [`Renderer::max_render_size`].
+    fn reserve_render_size(&mut self, _width: u32, _height: u32) -> Result<()> {
+        Ok(())
     }
 
-    let mut buffer = vec![0u8; (width * height * 3) as usize];
-
-    info!("Waiting for completion of graphics render queue");
-    graphics.Finish();
-    info!(
-        "Render completed. Total elapsed time to perform render: {:?}",
-        render_start_time.elapsed()
-    );
-
-    info!("Performing call to glReadPixels to fetch image from graphics memory");
-
-    let read_pixels_start_time = Instant::now();
-    graphics.ReadPixels(
-        0,
-        0,
-        width as GLsizei,
-        height as GLsizei,
-        gl::RGB,
-        gl::UNSIGNED_BYTE,
-        buffer.as_mut_ptr() as *mut _,
-    );
-    info!(
-        "Completed call glReadPixels in {:?}",
-        read_pixels_start_time.elapsed()
-    );
-
-    check_for_errors(&graphics);
-    match RgbImage::from_raw(width, height, buffer) {
-        Some(mut img) => {
-            flip_vertical_in_place(&mut img);
-            img
+    /// Draw the given blueprint to an image of exactly the requested size. The output size within
+    /// the options is ignored in favor of `width` and `height`.
+    fn draw(
+        &mut self,
+        blueprint: &Blueprint,
+        model_loader: &mut ModelLoader,
+        options: &RenderOptions,
+        width: u32,
+        height: u32,
+    ) -> Result<RgbaImage>;
+
+    /// Render the given blueprint using the provided options. This applies SSAA and resamples the
+    /// result to the size requested by the options.
+    fn render(
+        &mut self,
+        blueprint: &Blueprint,
+        model_loader: &mut ModelLoader,
+        options: &RenderOptions,
+    ) -> Result<RgbaImage> {
+        let mut ssaa = options.clamped_ssaa();
+
+        if options.ssaa > 16 {
+            warn!("SSAA values over 16 will not have a noticeable effect on the image quality");
         }
-        None => unreachable!("Buffer was created with the correct size"),
-    }
-}
-
-struct ModelGraphics {
-    vbo: GLuint,
-    vertex_count: GLsizei,
-    model_uniform: Mat4,
-    color_uniform: Vec3,
-}
 
-impl ModelGraphics {
-    fn ground_plane_vertex(inverse_camera: &Mat4, viewport_x: f32, viewport_y: f32) -> Vertex {
-        let frustum_near_corner = inverse_camera * Vec4::new(viewport_x, viewport_y, 0.0, 1.0);
-        let frustum_far_corner = inverse_camera * Vec4::new(viewport_x, viewport_y, 1.0, 1.0);
-
-        let frustum_near_corner = frustum_near_corner.xyz() / frustum_near_corner.w;
-        let frustum_far_corner = frustum_far_corner.xyz() / frustum_far_corner.w;
-
-        let offset = frustum_near_corner;
-        let direction = (frustum_far_corner - frustum_near_corner).normalize();
-
-        let ground_normal = Vec3::new(0.0, 1.0, 0.0);
+        let color_buffer_resolution = 1u32 << self.color_sample_size();
+        let msaa_samples = self.msaa_samples();
+        let max_useful_ssaa = (color_buffer_resolution as f64 / msaa_samples as f64)
+            .sqrt()
+            .ceil() as u32;
+
+        // The render target may have been created with the larger size, but we can still choose not
+        // to use the entirety of it for rendering with glViewport.
+        if ssaa > max_useful_ssaa {
+            warn!("The current SSAA setting combined with the system MSAA results in more samples being performed than the resolution of the color buffer. Reducing SSAA from {} to {}.", ssaa, max_useful_ssaa);
+            ssaa = max_useful_ssaa;
+        }
 
-        let denominator = ground_normal.dot(&direction);
-        if denominator.abs() < f32::EPSILON {
-            return Vertex::new(Vec3::default(), ground_normal);
+        if ssaa != 1 {
+            info!(
+                "Using SSAA to increase render samples by factor of {}",
+                ssaa
+            );
         }
 
-        let t = (-offset).dot(&ground_normal) / denominator;
-        Vertex::new(offset + direction * t, ground_normal)
-    }
+        let mut render_width = options.width.saturating_mul(ssaa);
+        let mut render_height = options.height.saturating_mul(ssaa);
+        self.reserve_render_size(render_width, render_height)?;
 
-    unsafe fn calculate_ground_plane(gl: &Gl, camera: Mat4) -> ModelGraphics {
-        let buffer = match camera.try_inverse() {
-            Some(inverse) => {
-                let a = Self::ground_plane_vertex(&inverse, 1.0, 1.0);
-                let b = Self::ground_plane_vertex(&inverse, -1.0, 1.0);
-                let c = Self::ground_plane_vertex(&inverse, 1.0, -1.0);
-                let d = Self::ground_plane_vertex(&inverse, -1.0, -1.0);
-
-                [a, b, c, b, c, d]
-            }
-            None => {
-                warn!("Unable to invert camera matrix");
-                let size = 1000.0;
-                let ground_normal = Vec3::new(0.0, 1.0, 0.0);
-
-                let a = Vertex::new(Vec3::new(size, 0.0, size), ground_normal);
-                let b = Vertex::new(Vec3::new(-size, 0.0, size), ground_normal);
-                let c = Vertex::new(Vec3::new(size, 0.0, -size), ground_normal);
-                let d = Vertex::new(Vec3::new(-size, 0.0, -size), ground_normal);
-
-                [a, b, c, b, c, d]
-            }
-        };
+        let (max_width, max_height) = self.max_render_size();
+        if render_width > max_width || render_height > max_height {
+            warn!(
+                "Render target provided by system is not large enough to render with the output size and SSAA"
+            );
 
-        ModelGraphics {
-            vbo: load_vbo(gl, &buffer),
-            vertex_count: 6,
-            model_uniform: Mat4::identity(),
-            color_uniform: Vec3::new(0.18039, 0.74902, 0.64706),
+            (render_width, render_height) =
+                clamp_with_aspect_ratio(render_width, render_height, max_width, max_height);
         }
-    }
-}
-
-/// Axis Aligned Bounding Box
-#[derive(Copy, Clone, Debug, Default)]
-struct Aabb {
-    min: Vec3,
-    max: Vec3,
-}
 
-impl Aabb {
-    pub fn expand_to_hold(&mut self, vertex: Vec3) {
-        self.min.x = self.min.x.min(vertex.x);
-        self.min.y = self.min.y.min(vertex.y);
-        self.min.z = self.min.z.min(vertex.z);
-
-        self.max.x = self.max.x.max(vertex.x);
-        self.max.y = self.max.y.max(vertex.y);
-        self.max.z = self.max.z.max(vertex.z);
-    }
+        let mut img = self.draw(
+            blueprint,
+            model_loader,
+            options,
+            render_width,
+            render_height,
+        )?;
+
+        if img.width() != options.width || img.height() != options.height {
+            let resample_filter = options.ssaa_sampler;
+
+            info!(
+                "Resampling image from render size ({}, {}) to desired size ({}, {}) using {:?} filter",
+                img.width(),
+                img.height(),
+                options.width,
+                options.height,
+                resample_filter
+            );
+
+            let resize_start_time = Instant::now();
+            img = resize(&img, options.width, options.height, resample_filter);
+            info!(
+                "Finished image resampling in {:?}",
+                resize_start_time.elapsed()
+            );
+        }
 
-    pub fn expand_to_hold_aabb(&mut self, other: Aabb) {
-        self.expand_to_hold(other.min);
-        self.expand_to_hold(other.max);
-    }
+        if options.background.w < 1.0 {
+            unpremultiply_alpha(&mut img);
+        }
 
-    pub fn corners(&self) -> [Vec3; 8] {
-        [
-            Vec3::new(self.min.x, self.min.y, self.min.z),
-            Vec3::new(self.min.x, self.min.y, self.max.z),
-            Vec3::new(self.min.x, self.max.y, self.min.z),
-            Vec3::new(self.min.x, self.max.y, self.max.z),
-            Vec3::new(self.max.x, self.min.y, self.min.z),
-            Vec3::new(self.max.x, self.min.y, self.max.z),
-            Vec3::new(self.max.x, self.max.y, self.min.z),
-            Vec3::new(self.max.x, self.max.y, self.max.z),
-        ]
+        Ok(img)
     }
+}
 
-    pub fn apply_transform(&self, transform: &Mat4) -> Aabb {
-        let transformed_corners = self
-            .corners()
-            .map(|corner| (transform * Vec4::new(corner.x, corner.y, corner.z, 1.0)).xyz());
-
-        let mut new_aabb = Aabb {
-            min: transformed_corners[0],
-            max: transformed_corners[0],
-        };
-
-        transformed_corners
-            .iter()
-            .for_each(|corner| new_aabb.expand_to_hold(*corner));
+/// Convert an image from premultiplied alpha to straight alpha, as expected by image formats.
+fn unpremultiply_alpha(img: &mut RgbaImage) {
+    for Rgba([r, g, b, a]) in img.pixels_mut() {
+        if *a == 0 || *a == u8::MAX {
+            continue;
+        }
 
-        new_aabb
+        for channel in [r, g, b] {
+            *channel = ((*channel as u32 * 255 + *a as u32 / 2) / *a as u32).min(255) as u8;
+        }
     }
 }
 
-unsafe fn send_models_to_gpu(
-    gl: &Gl,
-    entries: &[BlueprintEntry],
-    model_loader: &mut ModelLoader,
-) -> (Vec<ModelGraphics>, Aabb) {
-    let mut built_models: HashMap<*const Obj, (GLuint, GLsizei, Aabb)> =
-        HashMap::with_capacity(entries.len());
-    let mut models = Vec::with_capacity(entries.len());
-    let mut aabb = Aabb::default();
-
-    let mut model_vertex_buffer = Vec::new();
-
-    let mut vertex_build_time = Duration::default();
-    let mut aabb_build_time = Duration::default();
-    let mut gpu_upload_time = Duration::default();
-
-    // let blueprint_color = Vec3::new(0.18823, 0.51372, 0.86274);
-    let blueprint_color = Vec3::new(56.0, 171.0, 203.0) / 255.0;
-
-    for entry in entries {
-        for Model { model, offset } in model_loader.load_model(entry.internal_name()) {
-            let pos = translation(&entry.position());
-            let pos = scale(&pos, &Vec3::new(1.0, 1.0, -1.0));
-            let pos = rotate_y(&pos, entry.rotation());
-            let pos = translate(&pos, offset);
-
-            if let Some(&(vbo, vertex_count, model_aabb)) = built_models.get(&Rc::as_ptr(model)) {
-                let aabb_build_start_time = Instant::now();
-                aabb.expand_to_hold_aabb(model_aabb.apply_transform(&pos));
-                aabb_build_time += aabb_build_start_time.elapsed();
-
-                models.push(ModelGraphics {
-                    vbo,
-                    vertex_count,
-                    model_uniform: pos,
-                    color_uniform: blueprint_color,
-                });
-
-                continue;
-            }
-
-            let aabb_build_start_time = Instant::now();
-            let mut model_aabb = Aabb::default();
-            model
-                .data
-                .position
-                .iter()
-                .map(|array| Vec3::from(*array))
-                .for_each(|vertex| model_aabb.expand_to_hold(vertex));
-
-            aabb.expand_to_hold_aabb(model_aabb.apply_transform(&pos));
-            aabb_build_time += aabb_build_start_time.elapsed();
-
-            let vertex_build_start_time = Instant::now();
-            model_vertex_buffer.clear();
-            vertex_buffer_for_model(&mut model_vertex_buffer, model);
-            vertex_build_time += vertex_build_start_time.elapsed();
-
-            let vbo_creation_start_time = Instant::now();
-            let vbo = load_vbo(gl, &model_vertex_buffer);
-            gpu_upload_time += vbo_creation_start_time.elapsed();
-
-            let vertex_count = model_vertex_buffer.len() as GLsizei;
-            built_models.insert(Rc::as_ptr(model), (vbo, vertex_count, model_aabb));
-
-            models.push(ModelGraphics {
-                vbo,
-                vertex_count,
-                model_uniform: pos,
-                color_uniform: blueprint_color,
-            });
-        }
+fn clamp_with_aspect_ratio(
+    src_width: u32,
+    src_height: u32,
+    dst_width: u32,
+    dst_height: u32,
+) -> (u32, u32) {
+    if src_width <= dst_width && src_height <= dst_height {
+        return (src_width, src_height);
     }
 
-    info!("Sent model data to graphics memory:");
-    info!("Vertex list build time: {:?}", vertex_build_time);
-    info!("AABB build time: {:?}", aabb_build_time);
-    info!("GL buffer upload time: {:?}", gpu_upload_time);
+    // The products are taken in 64 bits so large sizes can not overflow. Each quotient is no
+    // larger than the destination size, so it always fits back into 32 bits.
+    let width = src_width as u64 * dst_height as u64;
+    let height = src_height as u64 * dst_width as u64;
 
-    (models, aabb)
+    if width > height {
+        (dst_width, (height / src_width as u64) as u32)
+    } else {
+        ((width / src_height as u64) as u32, dst_height)
+    }
 }
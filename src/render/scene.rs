@@ -0,0 +1,443 @@
+use crate::blueprint::{Blueprint, BlueprintEntry};
+use crate::building_config::BuildingConfig;
+use crate::error::Result;
+use crate::render::foundation::{island_foundation, FoundationPiece};
+use crate::render::label::{layout_label, Glyph};
+use crate::render::material::{fluid_color, is_fluid, Material, RenderStyle};
+use crate::render::stand::{support_stands, StandPiece};
+use crate::render::vertex::Vertex;
+use crate::tweaks::{Model, ModelLoader};
+use log::{info, warn};
+use nalgebra_glm::{rotate_y, scale, translation, Mat4, Vec3, Vec4};
+use obj::Obj;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// The color the render target is cleared to before drawing, unless another background is chosen
+pub const CLEAR_COLOR: Vec4 = Vec4::new(0.1, 0.1, 0.1, 1.0);
+
+/// The color used for all buildings within the blueprint
+pub const BLUEPRINT_COLOR: Vec3 = Vec3::new(56.0 / 255.0, 171.0 / 255.0, 203.0 / 255.0);
+
+/// The color used for the foundations of islands
+pub const FOUNDATION_COLOR: Vec3 = Vec3::new(34.0 / 255.0, 103.0 / 255.0, 122.0 / 255.0);
+
+/// The color of the text drawn on labels
+pub const LABEL_TEXT_COLOR: Vec3 = Vec3::new(0.92, 0.94, 0.96);
+
+/// The color of the ground plane drawn beneath the blueprint
+pub const GROUND_COLOR: Vec3 = Vec3::new(0.18039, 0.74902, 0.64706);
+
+/// The color of the ground plane in the realistic style
+pub const REALISTIC_GROUND_COLOR: Vec3 = Vec3::new(0.32, 0.36, 0.3);
+
+/// The direction of the directional light illuminating the scene
+pub fn light_direction() -> Vec3 {
+    Vec3::new(1.0, -2.0, 1.0).normalize()
+}
+
+/// Parse a color written as `#rrggbb` or `#rrggbbaa`, or the name `transparent`.
+pub fn parse_color(text: &str) -> std::result::Result<Vec4, String> {
+    if text == "transparent" {
+        return Ok(Vec4::zeros());
+    }
+
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let channels = match hex.len() {
+        6 | 8 if hex.is_ascii() => (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .ok(),
+        _ => None,
+    };
+
+    let channel = |x: u8| x as f32 / 255.0;
+    match channels.as_deref() {
+        Some(&[r, g, b]) => Ok(Vec4::new(channel(r), channel(g), channel(b), 1.0)),
+        Some(&[r, g, b, a]) => Ok(Vec4::new(channel(r), channel(g), channel(b), channel(a))),
+        _ => Err(format!(
+            "Invalid color {:?}: expected #rrggbb, #rrggbbaa or transparent",
+            text
+        )),
+    }
+}
+
+/// Get a color with the color channels multiplied by the alpha channel. Renders are produced
+/// with premultiplied alpha so that MSAA and SSAA blend partially covered pixels correctly.
+pub fn premultiply(color: Vec4) -> Vec4 {
+    Vec4::new(
+        color.x * color.w,
+        color.y * color.w,
+        color.z * color.w,
+        color.w,
+    )
+}
+
+/// A model placed into the world by a blueprint entry
+pub struct PlacedModel {
+    pub model: Rc<Obj>,
+    pub transform: Mat4,
+    pub material: Material,
+}
+
+/// Get the material used to draw a model. In the blueprint style, models are drawn in a single
+/// color unless the mapping gives them another. In the realistic style, the material is found
+/// from the material table, with the color of the mapping applied on top. Fluids without a color
+/// from the mapping are tinted by `fluid`, the color of the fluid held by the building.
+fn model_material(
+    style: RenderStyle,
+    model: &Model,
+    blueprint_color: Vec3,
+    fluid: Option<Vec3>,
+) -> Material {
+    match style {
+        RenderStyle::Blueprint => Material::flat(model.color.unwrap_or(blueprint_color)),
+        RenderStyle::Realistic => {
+            let material = Material::for_name(&model.material);
+            let fluid = fluid.filter(|_| is_fluid(&model.material));
+            model
+                .color
+                .or(fluid)
+                .map_or(material, |color| material.with_color(color))
+        }
+    }
+}
+
+/// Order draws so every opaque mesh comes first, followed by the translucent meshes from back to
+/// front as seen from the camera. Translucent meshes are ordered by the origin of their model, so
+/// the parts of a building stay together. Parts sharing an origin, such as the fluid within a glass
+/// shell, are drawn from the most to the least opaque.
+pub fn sort_for_blending<T>(draws: &mut [T], camera: Vec3, key: impl Fn(&T) -> (&Mat4, &Material)) {
+    let distance = |transform: &Mat4| (transform.column(3).xyz() - camera).norm();
+
+    draws.sort_by(|a, b| {
+        let (a_transform, a_material) = key(a);
+        let (b_transform, b_material) = key(b);
+
+        match (a_material.is_translucent(), b_material.is_translucent()) {
+            (false, false) => Ordering::Equal,
+            (false, true) => Ordering::Less,
+            (true, false) => Ordering::Greater,
+            (true, true) => distance(b_transform)
+                .total_cmp(&distance(a_transform))
+                .then(b_material.opacity.total_cmp(&a_material.opacity)),
+        }
+    });
+}
+
+/// Get the material of the ground plane drawn beneath the blueprint
+pub fn ground_material(style: RenderStyle) -> Material {
+    match style {
+        RenderStyle::Blueprint => Material::flat(GROUND_COLOR),
+        RenderStyle::Realistic => Material::flat(REALISTIC_GROUND_COLOR),
+    }
+}
+
+/// Get the world transform for a model belonging to the given entry, where `local` is the
+/// transform of the model within the building.
+pub fn model_transform(entry: &BlueprintEntry, local: &Mat4) -> Mat4 {
+    let pos = translation(&entry.position());
+    let pos = scale(&pos, &Vec3::new(1.0, 1.0, -1.0));
+    let pos = rotate_y(&pos, entry.rotation());
+    pos * local
+}
+
+/// The models making up a blueprint, placed in the world
+pub struct Scene {
+    pub models: Vec<PlacedModel>,
+    /// The bounds of every placed model
+    pub aabb: Aabb,
+    /// The height of the ground plane. The ground lies beneath the foundations of islands, so
+    /// they are not hidden by it.
+    pub ground_height: f32,
+}
+
+/// Resolve the models used by each entry and place them in the world. Labels also receive a model
+/// for each glyph of their text, buildings receive stands reaching down to whatever lies beneath
+/// them, and islands receive the models making up their foundation.
+pub fn place_models(
+    blueprint: &Blueprint,
+    model_loader: &mut ModelLoader,
+    style: RenderStyle,
+) -> Result<Scene> {
+    let entries = &blueprint[..];
+    let mut model_bounds: HashMap<*const Obj, Aabb> = HashMap::with_capacity(entries.len());
+    let mut models = Vec::with_capacity(entries.len());
+    let mut aabb = Aabb::default();
+
+    let aabb_build_start_time = Instant::now();
+    for entry in entries {
+        // Include the floor of every tile covered by the building, so buildings without models
+        // still have space left for them
+        let ((min_x, min_y, min_layer), (max_x, max_y, _)) = entry.bounds();
+        aabb.expand_to_hold(Vec3::new(
+            min_x as f32 - 0.5,
+            min_layer as f32,
+            min_y as f32 - 0.5,
+        ));
+        aabb.expand_to_hold(Vec3::new(
+            max_x as f32 + 0.5,
+            min_layer as f32,
+            max_y as f32 + 0.5,
+        ));
+
+        let config = entry.config();
+        let fluid = config.fluid().and_then(fluid_color);
+
+        for placed in model_loader.load_model(entry.internal_name())? {
+            // Blueprints do not hold fluids, so they are only drawn in the realistic style
+            if style == RenderStyle::Blueprint && is_fluid(&placed.material) {
+                continue;
+            }
+
+            let model = &placed.model;
+            let transform = model_transform(entry, &placed.transform);
+
+            let model_aabb = *model_bounds
+                .entry(Rc::as_ptr(model))
+                .or_insert_with(|| Aabb::for_model(model));
+            aabb.expand_to_hold_aabb(model_aabb.apply_transform(&transform));
+
+            models.push(PlacedModel {
+                model: model.clone(),
+                transform,
+                material: model_material(style, placed, BLUEPRINT_COLOR, fluid),
+            });
+        }
+
+        if let BuildingConfig::Label { text } = config {
+            for Glyph { model, transform } in layout_label(&text, model_loader, &mut model_bounds)?
+            {
+                let transform = model_transform(entry, &transform);
+
+                let model_aabb = model_bounds[&Rc::as_ptr(&model)];
+                aabb.expand_to_hold_aabb(model_aabb.apply_transform(&transform));
+
+                models.push(PlacedModel {
+                    model,
+                    transform,
+                    material: Material::flat(LABEL_TEXT_COLOR),
+                });
+            }
+        }
+    }
+
+    for StandPiece { model, transform } in support_stands(blueprint, model_loader)? {
+        for placed in model_loader.load_model(model)? {
+            let model = &placed.model;
+            let transform = transform * placed.transform;
+
+            let model_aabb = *model_bounds
+                .entry(Rc::as_ptr(model))
+                .or_insert_with(|| Aabb::for_model(model));
+            aabb.expand_to_hold_aabb(model_aabb.apply_transform(&transform));
+
+            models.push(PlacedModel {
+                model: model.clone(),
+                transform,
+                material: model_material(style, placed, BLUEPRINT_COLOR, None),
+            });
+        }
+    }
+
+    let mut ground_height = 0.0;
+    for island in blueprint.islands() {
+        for FoundationPiece { model, transform } in island_foundation(island) {
+            for placed in model_loader.load_model(model)? {
+                let model = &placed.model;
+                let transform = transform * placed.transform;
+
+                let model_aabb = *model_bounds
+                    .entry(Rc::as_ptr(model))
+                    .or_insert_with(|| Aabb::for_model(model));
+                let world_aabb = model_aabb.apply_transform(&transform);
+                aabb.expand_to_hold_aabb(world_aabb);
+                ground_height = f32::min(ground_height, world_aabb.min.y);
+
+                models.push(PlacedModel {
+                    model: model.clone(),
+                    transform,
+                    material: model_material(style, placed, FOUNDATION_COLOR, None),
+                });
+            }
+        }
+    }
+
+    info!("AABB build time: {:?}", aabb_build_start_time.elapsed());
+    Ok(Scene {
+        models,
+        aabb,
+        ground_height,
+    })
+}
+
+fn ground_plane_vertex(
+    inverse_camera: &Mat4,
+    height: f32,
+    viewport_x: f32,
+    viewport_y: f32,
+) -> Vertex {
+    let frustum_near_corner = inverse_camera * Vec4::new(viewport_x, viewport_y, 0.0, 1.0);
+    let frustum_far_corner = inverse_camera * Vec4::new(viewport_x, viewport_y, 1.0, 1.0);
+
+    let frustum_near_corner = frustum_near_corner.xyz() / frustum_near_corner.w;
+    let frustum_far_corner = frustum_far_corner.xyz() / frustum_far_corner.w;
+
+    let offset = frustum_near_corner;
+    let direction = (frustum_far_corner - frustum_near_corner).normalize();
+
+    let ground_normal = Vec3::new(0.0, 1.0, 0.0);
+
+    let denominator = ground_normal.dot(&direction);
+    if denominator.abs() < f32::EPSILON {
+        return Vertex::new(Vec3::default(), ground_normal);
+    }
+
+    let t = (height - offset.dot(&ground_normal)) / denominator;
+    Vertex::new(offset + direction * t, ground_normal)
+}
+
+/// Build the triangles for a ground plane at the given height which covers the entire view of the
+/// camera.
+pub fn ground_plane(camera: Mat4, height: f32) -> [Vertex; 6] {
+    match camera.try_inverse() {
+        Some(inverse) => {
+            let a = ground_plane_vertex(&inverse, height, 1.0, 1.0);
+            let b = ground_plane_vertex(&inverse, height, -1.0, 1.0);
+            let c = ground_plane_vertex(&inverse, height, 1.0, -1.0);
+            let d = ground_plane_vertex(&inverse, height, -1.0, -1.0);
+
+            [a, b, c, b, c, d]
+        }
+        None => {
+            warn!("Unable to invert camera matrix");
+            let size = 1000.0;
+            let ground_normal = Vec3::new(0.0, 1.0, 0.0);
+
+            let a = Vertex::new(Vec3::new(size, height, size), ground_normal);
+            let b = Vertex::new(Vec3::new(-size, height, size), ground_normal);
+            let c = Vertex::new(Vec3::new(size, height, -size), ground_normal);
+            let d = Vertex::new(Vec3::new(-size, height, -size), ground_normal);
+
+            [a, b, c, b, c, d]
+        }
+    }
+}
+
+/// Axis Aligned Bounding Box
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Get the bounding box holding all vertices of a model in model space.
+    pub fn for_model(model: &Obj) -> Self {
+        let mut model_aabb = Aabb::default();
+        model
+            .data
+            .position
+            .iter()
+            .map(|array| Vec3::from(*array))
+            .for_each(|vertex| model_aabb.expand_to_hold(vertex));
+        model_aabb
+    }
+
+    pub fn expand_to_hold(&mut self, vertex: Vec3) {
+        self.min.x = self.min.x.min(vertex.x);
+        self.min.y = self.min.y.min(vertex.y);
+        self.min.z = self.min.z.min(vertex.z);
+
+        self.max.x = self.max.x.max(vertex.x);
+        self.max.y = self.max.y.max(vertex.y);
+        self.max.z = self.max.z.max(vertex.z);
+    }
+
+    pub fn expand_to_hold_aabb(&mut self, other: Aabb) {
+        self.expand_to_hold(other.min);
+        self.expand_to_hold(other.max);
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        [
+            Vec3::new(self.min.x, self.min.y, self.min.z),
+            Vec3::new(self.min.x, self.min.y, self.max.z),
+            Vec3::new(self.min.x, self.max.y, self.min.z),
+            Vec3::new(self.min.x, self.max.y, self.max.z),
+            Vec3::new(self.max.x, self.min.y, self.min.z),
+            Vec3::new(self.max.x, self.min.y, self.max.z),
+            Vec3::new(self.max.x, self.max.y, self.min.z),
+            Vec3::new(self.max.x, self.max.y, self.max.z),
+        ]
+    }
+
+    pub fn apply_transform(&self, transform: &Mat4) -> Aabb {
+        let transformed_corners = self
+            .corners()
+            .map(|corner| (transform * Vec4::new(corner.x, corner.y, corner.z, 1.0)).xyz());
+
+        let mut new_aabb = Aabb {
+            min: transformed_corners[0],
+            max: transformed_corners[0],
+        };
+
+        transformed_corners
+            .iter()
+            .for_each(|corner| new_aabb.expand_to_hold(*corner));
+
+        new_aabb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn place(style: RenderStyle, json: &str) -> Scene {
+        let blueprint = Blueprint::from_json(json.as_bytes()).unwrap();
+        let mut model_loader =
+            ModelLoader::new(concat!(env!("CARGO_MANIFEST_DIR"), "/models")).unwrap();
+        place_models(&blueprint, &mut model_loader, style).unwrap()
+    }
+
+    #[test]
+    fn parts_of_a_building_have_their_own_materials() {
+        let pipe =
+            r#"{"V":1122,"BP":{"Entries":[{"X":0,"Y":0,"T":"PipeForwardInternalVariant"}]}}"#;
+
+        // The models of the building are placed before its support stand
+        let realistic = place(RenderStyle::Realistic, pipe);
+        let materials: Vec<Material> = realistic.models[..2]
+            .iter()
+            .map(|model| model.material)
+            .collect();
+        assert_eq!(
+            materials,
+            [
+                Material::for_name("PipeForwardGlas"),
+                Material::for_name("PipeForwardFluid")
+            ]
+        );
+        assert_ne!(materials[0], materials[1]);
+
+        // Blueprints do not hold fluids, so only the glass is drawn in the blueprint style
+        let blueprint = place(RenderStyle::Blueprint, pipe);
+        assert_eq!(blueprint.models.len(), realistic.models.len() - 1);
+    }
+
+    #[test]
+    fn belt_parts_have_their_own_materials() {
+        assert_ne!(
+            Material::for_name("Belt_Belt"),
+            Material::for_name("Belt_Borders")
+        );
+        assert_ne!(
+            Material::for_name("Mergers and splitters_Belt"),
+            Material::for_name("Mergers and splitters_Borders")
+        );
+    }
+}
@@ -70,10 +70,10 @@ fn check_for_program_errors(gl: &Gl, program: GLuint) -> Result<(), ShaderError>
             buffer.as_mut_ptr() as *mut GLchar,
         );
 
-        return Err(ShaderError {
+        Err(ShaderError {
             code,
             msg: String::from_utf8_lossy(&buffer[..length as usize]).into_owned(),
-        });
+        })
     }
 }
 
@@ -96,10 +96,10 @@ fn check_for_shader_errors(gl: &Gl, shader: GLuint) -> Result<(), ShaderError> {
             buffer.as_mut_ptr() as *mut GLchar,
         );
 
-        return Err(ShaderError {
+        Err(ShaderError {
             code,
             msg: String::from_utf8_lossy(&buffer[..length as usize]).into_owned(),
-        });
+        })
     }
 }
 
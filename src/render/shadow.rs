@@ -0,0 +1,235 @@
+use crate::c_str;
+use crate::error::Result;
+use crate::render::gl::types::{GLint, GLsizei, GLuint};
+use crate::render::scene::{light_direction, Aabb};
+use crate::render::shader::build_program;
+use crate::render::util::check_for_errors;
+use crate::render::{gl, Gl};
+use clap::ValueEnum;
+use log::info;
+use nalgebra_glm::{look_at, ortho, Mat4, Vec3, Vec4};
+use serde::Deserialize;
+
+/// The fraction of direct light blocked within a shadow. Shadows are not fully dark, as the
+/// scene is also lit by ambient light.
+pub const SHADOW_STRENGTH: f32 = 0.6;
+
+/// The distance in world units by which surfaces are moved towards the light before being
+/// compared against the shadow map, so surfaces do not shadow themselves
+const SHADOW_BIAS: f32 = 0.02;
+
+/// The resolution and filtering of shadows
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ShadowQuality {
+    /// A 1024x1024 shadow map
+    Low,
+    /// A 2048x2048 shadow map
+    #[default]
+    Medium,
+    /// A 4096x4096 shadow map with wider filtering for softer edges
+    High,
+}
+
+impl ShadowQuality {
+    /// The width and height of the shadow map
+    pub fn map_size(self) -> u32 {
+        match self {
+            ShadowQuality::Low => 1024,
+            ShadowQuality::Medium => 2048,
+            ShadowQuality::High => 4096,
+        }
+    }
+
+    /// The number of texels sampled on each side of a point when softening the edges of shadows
+    /// with percentage closer filtering
+    pub fn filter_radius(self) -> i32 {
+        match self {
+            ShadowQuality::Low | ShadowQuality::Medium => 1,
+            ShadowQuality::High => 2,
+        }
+    }
+}
+
+/// The view of the scene from the directional light, used to draw and sample the shadow map
+#[derive(Copy, Clone, Debug)]
+pub struct LightSpace {
+    /// The transform from world space into the clip space of the shadow map
+    pub transform: Mat4,
+    /// The depth bias applied when sampling the shadow map, in the depth range of the map
+    pub bias: f32,
+}
+
+impl LightSpace {
+    /// Fit an orthographic projection along the light direction around the bounding box of the
+    /// scene and the ground beneath it.
+    pub fn fit_to_aabb(aabb: Aabb, ground_height: f32) -> Self {
+        let mut bounds = aabb;
+        bounds.expand_to_hold(Vec3::new(aabb.min.x, ground_height, aabb.min.z));
+
+        let direction = light_direction();
+        let center = (bounds.min + bounds.max) * 0.5;
+        let view = look_at(&(center - direction), &center, &Vec3::y());
+
+        let mut light_bounds = Aabb {
+            min: Vec3::repeat(f32::INFINITY),
+            max: Vec3::repeat(f32::NEG_INFINITY),
+        };
+        for corner in bounds.corners() {
+            light_bounds
+                .expand_to_hold((view * Vec4::new(corner.x, corner.y, corner.z, 1.0)).xyz());
+        }
+
+        // Keep a small margin so surfaces lying on the bounds are not clipped
+        let margin = 0.5;
+        let near = -light_bounds.max.z - margin;
+        let far = -light_bounds.min.z + margin;
+        let projection = ortho(
+            light_bounds.min.x - margin,
+            light_bounds.max.x + margin,
+            light_bounds.min.y - margin,
+            light_bounds.max.y + margin,
+            near,
+            far,
+        );
+
+        LightSpace {
+            transform: projection * view,
+            bias: SHADOW_BIAS / (far - near),
+        }
+    }
+}
+
+/// A depth texture holding the distance from the light to the nearest surface
+pub struct ShadowMap {
+    pub size: u32,
+    framebuffer: GLuint,
+    texture: GLuint,
+}
+
+impl ShadowMap {
+    pub unsafe fn new(gl: &Gl, size: u32) -> Result<Self> {
+        info!("Creating shadow map of size ({}, {})", size, size);
+
+        let mut texture = 0;
+        gl.GenTextures(1, &mut texture);
+        gl.BindTexture(gl::TEXTURE_2D, texture);
+        gl.TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::DEPTH_COMPONENT24 as GLint,
+            size as GLsizei,
+            size as GLsizei,
+            0,
+            gl::DEPTH_COMPONENT,
+            gl::FLOAT,
+            std::ptr::null(),
+        );
+
+        // Linear filtering of a comparison sampler blends the results of the nearest texels
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+        gl.TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_COMPARE_MODE,
+            gl::COMPARE_REF_TO_TEXTURE as GLint,
+        );
+        gl.TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_COMPARE_FUNC,
+            gl::LEQUAL as GLint,
+        );
+
+        // Anything outside of the map is lit
+        gl.TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_WRAP_S,
+            gl::CLAMP_TO_BORDER as GLint,
+        );
+        gl.TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_WRAP_T,
+            gl::CLAMP_TO_BORDER as GLint,
+        );
+        let border = [1.0f32; 4];
+        gl.TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+
+        let mut framebuffer = 0;
+        gl.GenFramebuffers(1, &mut framebuffer);
+        gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+        gl.FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_ATTACHMENT,
+            gl::TEXTURE_2D,
+            texture,
+            0,
+        );
+        gl.DrawBuffers(1, &gl::NONE);
+        gl.ReadBuffer(gl::NONE);
+
+        check_for_errors(gl)?;
+        Ok(ShadowMap {
+            size,
+            framebuffer,
+            texture,
+        })
+    }
+
+    /// Release the graphics memory held by this shadow map.
+    pub unsafe fn delete(self, gl: &Gl) {
+        gl.DeleteFramebuffers(1, &self.framebuffer);
+        gl.DeleteTextures(1, &self.texture);
+    }
+
+    /// Bind the shadow map as the target for the following draw calls and clear it.
+    pub unsafe fn bind_for_drawing(&self, gl: &Gl) {
+        gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        gl.Viewport(0, 0, self.size as GLsizei, self.size as GLsizei);
+        gl.Clear(gl::DEPTH_BUFFER_BIT);
+    }
+
+    /// Bind the shadow map to a texture unit so it can be sampled.
+    pub unsafe fn bind_texture(&self, gl: &Gl, unit: GLuint) {
+        gl.ActiveTexture(gl::TEXTURE0 + unit);
+        gl.BindTexture(gl::TEXTURE_2D, self.texture);
+    }
+}
+
+/// The program used to draw the depth of the scene into the shadow map
+pub struct ShadowProgram {
+    pub program: GLuint,
+    model: GLint,
+    light_space: GLint,
+}
+
+impl ShadowProgram {
+    pub unsafe fn build(gl: &Gl) -> Result<Self> {
+        let vert = c_str!(include_str!("../shadow_vert.glsl"));
+        let frag = c_str!(include_str!("../shadow_frag.glsl"));
+
+        let program = build_program(gl, vert, frag)?;
+        gl.UseProgram(program);
+
+        let shadow_program = ShadowProgram {
+            program,
+            model: gl.GetUniformLocation(program, c_str!("model").as_ptr()),
+            light_space: gl.GetUniformLocation(program, c_str!("lightSpace").as_ptr()),
+        };
+
+        check_for_errors(gl)?;
+        Ok(shadow_program)
+    }
+
+    pub unsafe fn set_model(&self, gl: &Gl, model: &Mat4) {
+        gl.UniformMatrix4fv(self.model, 1, gl::FALSE, model.as_ptr() as *const _);
+    }
+
+    pub unsafe fn set_light_space(&self, gl: &Gl, light_space: &Mat4) {
+        gl.UniformMatrix4fv(
+            self.light_space,
+            1,
+            gl::FALSE,
+            light_space.as_ptr() as *const _,
+        );
+    }
+}
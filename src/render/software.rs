@@ -0,0 +1,597 @@
+use crate::blueprint::Blueprint;
+use crate::error::Result;
+use crate::render::camera::Camera;
+use crate::render::material::Material;
+use crate::render::scene::{
+    ground_material, ground_plane, light_direction, place_models, premultiply, sort_for_blending,
+    PlacedModel, BLUEPRINT_COLOR,
+};
+use crate::render::shadow::{LightSpace, SHADOW_STRENGTH};
+use crate::render::vertex::{vertex_buffer_for_model, Vertex};
+use crate::render::{RenderOptions, Renderer};
+use crate::tweaks::ModelLoader;
+use image::{Rgba, RgbaImage};
+use log::{info, warn};
+use nalgebra_glm::{Mat4, Vec3, Vec4};
+use obj::Obj;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// A pure software rasterizer. This produces the same images as the OpenGL renderer (minus any
+/// MSAA), but does not require any graphics drivers to be present on the system.
+#[derive(Default)]
+pub struct SoftwareRenderer;
+
+impl SoftwareRenderer {
+    pub fn new() -> Self {
+        SoftwareRenderer
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn max_render_size(&self) -> (u32, u32) {
+        // Limit the size such that the color and depth buffers can always be indexed by a u32
+        (1 << 15, 1 << 15)
+    }
+
+    fn draw(
+        &mut self,
+        blueprint: &Blueprint,
+        model_loader: &mut ModelLoader,
+        options: &RenderOptions,
+        width: u32,
+        height: u32,
+    ) -> Result<RgbaImage> {
+        let render_start_time = Instant::now();
+        info!("Beginning software render of size ({}, {})", width, height);
+
+        if options.ssao {
+            warn!(
+                "Ambient occlusion is not supported by the software renderer and will not be drawn"
+            );
+        }
+
+        let scene = place_models(blueprint, model_loader, options.style)?;
+        let camera = Camera::fit_to_aabb(
+            scene.aabb,
+            scene.ground_height,
+            width,
+            height,
+            &options.camera,
+        );
+        let view_projection = camera.projection * camera.view;
+
+        let mut target = RenderTarget::new(width, height, options.background);
+
+        let mut vertex_buffers: HashMap<*const Obj, Vec<Vertex>> = HashMap::new();
+        let mut vertex_build_time = Duration::default();
+
+        let raster_start_time = Instant::now();
+        for PlacedModel { model, .. } in &scene.models {
+            vertex_buffers.entry(Rc::as_ptr(model)).or_insert_with(|| {
+                let vertex_build_start_time = Instant::now();
+                let mut buffer = Vec::new();
+                vertex_buffer_for_model(&mut buffer, model);
+                vertex_build_time += vertex_build_start_time.elapsed();
+                buffer
+            });
+        }
+
+        let mut draws: Vec<(Mat4, &[Vertex], Material)> = Vec::new();
+        for PlacedModel {
+            model,
+            transform,
+            material,
+        } in &scene.models
+        {
+            draws.push((*transform, &vertex_buffers[&Rc::as_ptr(model)], *material));
+        }
+
+        // Translucent meshes let light through, so they do not cast shadows
+        let shadows = options.shadows.then(|| {
+            let shadow_start_time = Instant::now();
+            let mut shadows = ShadowMap::new(
+                LightSpace::fit_to_aabb(scene.aabb, scene.ground_height),
+                options.shadow_quality.map_size(),
+                options.shadow_quality.filter_radius(),
+            );
+            for (transform, vertices, material) in &draws {
+                if !material.is_translucent() {
+                    shadows.draw_triangles(transform, vertices);
+                }
+            }
+            info!("Drew shadow map in {:?}", shadow_start_time.elapsed());
+            shadows
+        });
+
+        let mut shading = Shading {
+            camera: camera.position,
+            light_direction: light_direction(),
+            material: Material::flat(BLUEPRINT_COLOR),
+            shadows,
+        };
+
+        let ground = ground_plane(view_projection, scene.ground_height);
+        if options.ground_plane {
+            draws.push((Mat4::identity(), &ground, ground_material(options.style)));
+        }
+
+        // Translucent meshes are blended over everything drawn before them, so they are drawn last.
+        // They do not write to the depth buffer, so they never hide each other.
+        sort_for_blending(&mut draws, camera.position, |(transform, _, material)| {
+            (transform, material)
+        });
+
+        for (transform, vertices, material) in draws {
+            shading.material = material;
+            target.draw_triangles(&view_projection, &transform, vertices, &shading);
+        }
+
+        info!("Vertex list build time: {:?}", vertex_build_time);
+        info!("Rasterization time: {:?}", raster_start_time.elapsed());
+        info!(
+            "Render completed. Total elapsed time to perform render: {:?}",
+            render_start_time.elapsed()
+        );
+
+        Ok(target.color)
+    }
+}
+
+/// The inputs to the lighting model. This mirrors the uniforms of `frag.glsl`.
+struct Shading {
+    camera: Vec3,
+    light_direction: Vec3,
+    material: Material,
+    shadows: Option<ShadowMap>,
+}
+
+impl Shading {
+    /// Equivalent to the fragment shader used by the OpenGL renderer. The color is returned with
+    /// premultiplied alpha.
+    fn shade(&self, position: Vec3, normal: Vec3, light_position: Vec3) -> Vec4 {
+        let Material {
+            color,
+            roughness,
+            emission,
+            opacity,
+        } = self.material;
+
+        // Fully rough materials keep the soft shading of the blueprint style
+        let shininess = 16.0 + (0.5 - 16.0) * roughness;
+        let ambient_light = 0.1;
+
+        let view_direction = (self.camera - position).normalize();
+        let halfway_dir = (-self.light_direction + view_direction).normalize();
+
+        let spec = normal.dot(&halfway_dir).max(0.0).powf(shininess);
+        let specular = color * spec;
+
+        let ambient = color * ambient_light;
+
+        // Smooth materials also reflect a white highlight
+        let highlight = normal.dot(&halfway_dir).max(0.0).powf(64.0) * (1.0 - roughness);
+
+        let emitted = color * emission;
+
+        let mut total_light = specular + ambient + Vec3::repeat(highlight) + emitted;
+
+        // Shadows only block the direct light
+        if let Some(shadows) = &self.shadows {
+            total_light -= (specular + Vec3::repeat(highlight))
+                * SHADOW_STRENGTH
+                * shadows.shadow(light_position);
+        }
+
+        // Gamma correction
+        let gamma = 2.2;
+        let rgb = total_light.map(|x| x.powf(1.0 / gamma)) * opacity;
+        Vec4::new(rgb.x, rgb.y, rgb.z, opacity)
+    }
+}
+
+/// The depth of the scene as seen from the light. This mirrors the shadow map of the OpenGL
+/// renderer, including the sampling performed by `frag.glsl`.
+struct ShadowMap {
+    light_space: LightSpace,
+    size: u32,
+    filter_radius: i32,
+    depth: Vec<f32>,
+}
+
+impl ShadowMap {
+    fn new(light_space: LightSpace, size: u32, filter_radius: i32) -> Self {
+        ShadowMap {
+            light_space,
+            size,
+            filter_radius,
+            depth: vec![1.0; size as usize * size as usize],
+        }
+    }
+
+    /// Draw the depth of each triangle. The light space is orthographic, so no clipping or
+    /// perspective correction is needed.
+    fn draw_triangles(&mut self, transform: &Mat4, vertices: &[Vertex]) {
+        let mvp = self.light_space.transform * transform;
+        let size = self.size as f32;
+
+        for triangle in vertices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|index| {
+                let position = triangle[index].position();
+                let ndc = (mvp * Vec4::new(position.x, position.y, position.z, 1.0)).xyz();
+                Vec3::new(
+                    (ndc.x + 1.0) * 0.5 * size,
+                    (ndc.y + 1.0) * 0.5 * size,
+                    ndc.z * 0.5 + 0.5,
+                )
+            });
+
+            let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+            if area.abs() < f32::EPSILON {
+                continue;
+            }
+
+            // Equivalent to glPolygonOffset(2.0, 4.0) with a 24-bit depth buffer
+            let depth_x = ((b.z - a.z) * (c.y - a.y) - (c.z - a.z) * (b.y - a.y)) / area;
+            let depth_y = ((c.z - a.z) * (b.x - a.x) - (b.z - a.z) * (c.x - a.x)) / area;
+            let offset = 2.0 * depth_x.abs().max(depth_y.abs()) + 4.0 / (1 << 24) as f32;
+
+            let min_x = a.x.min(b.x).min(c.x).floor().clamp(0.0, size) as u32;
+            let min_y = a.y.min(b.y).min(c.y).floor().clamp(0.0, size) as u32;
+            let max_x = a.x.max(b.x).max(c.x).ceil().clamp(0.0, size) as u32;
+            let max_y = a.y.max(b.y).max(c.y).ceil().clamp(0.0, size) as u32;
+
+            for y in min_y..max_y {
+                let sample_y = y as f32 + 0.5;
+
+                for x in min_x..max_x {
+                    let sample_x = x as f32 + 0.5;
+
+                    let weight_a =
+                        ((c.x - b.x) * (sample_y - b.y) - (c.y - b.y) * (sample_x - b.x)) / area;
+                    let weight_b =
+                        ((a.x - c.x) * (sample_y - c.y) - (a.y - c.y) * (sample_x - c.x)) / area;
+                    let weight_c = 1.0 - weight_a - weight_b;
+
+                    if weight_a < 0.0 || weight_b < 0.0 || weight_c < 0.0 {
+                        continue;
+                    }
+
+                    let depth = weight_a * a.z + weight_b * b.z + weight_c * c.z + offset;
+                    let index = y as usize * self.size as usize + x as usize;
+                    if (0.0..1.0).contains(&depth) && depth < self.depth[index] {
+                        self.depth[index] = depth;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The fraction of light from the light source which is blocked before reaching a point, given
+    /// in the clip space of the light.
+    fn shadow(&self, light_position: Vec3) -> f32 {
+        let position = light_position * 0.5 + Vec3::repeat(0.5);
+        if position.z > 1.0 {
+            return 0.0;
+        }
+
+        // Percentage closer filtering averages the comparison over the surrounding texels
+        let reference = position.z - self.light_space.bias;
+        let mut lit = 0.0;
+        for x in -self.filter_radius..=self.filter_radius {
+            for y in -self.filter_radius..=self.filter_radius {
+                lit += self.compare(
+                    position.x * self.size as f32 + x as f32,
+                    position.y * self.size as f32 + y as f32,
+                    reference,
+                );
+            }
+        }
+
+        let samples = (2 * self.filter_radius + 1).pow(2) as f32;
+        1.0 - lit / samples
+    }
+
+    /// Compare a depth against the nearest four texels to a point given in texels, blending the
+    /// results as with a linearly filtered `sampler2DShadow`.
+    fn compare(&self, x: f32, y: f32, reference: f32) -> f32 {
+        let x = x - 0.5;
+        let y = y - 0.5;
+        let (fraction_x, fraction_y) = (x - x.floor(), y - y.floor());
+        let (x, y) = (x.floor() as i64, y.floor() as i64);
+
+        let reference = reference.clamp(0.0, 1.0);
+        let lit = |x: i64, y: i64| {
+            // Anything outside of the map is lit
+            let size = self.size as i64;
+            let depth = match (0..size).contains(&x) && (0..size).contains(&y) {
+                true => self.depth[(y * size + x) as usize],
+                false => 1.0,
+            };
+            (reference <= depth) as u8 as f32
+        };
+
+        let top = lit(x, y) * (1.0 - fraction_x) + lit(x + 1, y) * fraction_x;
+        let bottom = lit(x, y + 1) * (1.0 - fraction_x) + lit(x + 1, y + 1) * fraction_x;
+        top * (1.0 - fraction_y) + bottom * fraction_y
+    }
+}
+
+/// Convert a color channel to an 8-bit normalized integer the same way OpenGL would.
+fn unorm8(x: f32) -> u8 {
+    (x.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// A vertex after it has been transformed into clip space
+#[derive(Copy, Clone)]
+struct ClipVertex {
+    clip: Vec4,
+    position: Vec3,
+    normal: Vec3,
+    /// The position in the clip space of the shadow map
+    light_position: Vec3,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        ClipVertex {
+            clip: self.clip.lerp(&other.clip, t),
+            position: self.position.lerp(&other.position, t),
+            normal: self.normal.lerp(&other.normal, t),
+            light_position: self.light_position.lerp(&other.light_position, t),
+        }
+    }
+
+    /// Signed distance to the near clipping plane. Points are visible when this is positive.
+    fn near_plane_distance(&self) -> f32 {
+        self.clip.z + self.clip.w
+    }
+}
+
+/// A vertex after the perspective divide and viewport transform
+#[derive(Copy, Clone)]
+struct ScreenVertex {
+    x: f32,
+    y: f32,
+    depth: f32,
+    inverse_w: f32,
+    position: Vec3,
+    normal: Vec3,
+    light_position: Vec3,
+}
+
+struct RenderTarget {
+    color: RgbaImage,
+    depth: Vec<f32>,
+}
+
+impl RenderTarget {
+    fn new(width: u32, height: u32, background: Vec4) -> Self {
+        let clear_color = Rgba(premultiply(background).map(unorm8).into());
+
+        RenderTarget {
+            color: RgbaImage::from_pixel(width, height, clear_color),
+            depth: vec![1.0; width as usize * height as usize],
+        }
+    }
+
+    fn draw_triangles(
+        &mut self,
+        view_projection: &Mat4,
+        transform: &Mat4,
+        vertices: &[Vertex],
+        shading: &Shading,
+    ) {
+        let mvp = view_projection * transform;
+        let light_mvp = match &shading.shadows {
+            Some(shadows) => shadows.light_space.transform * transform,
+            None => Mat4::identity(),
+        };
+
+        for triangle in vertices.chunks_exact(3) {
+            let clip_vertices = [0, 1, 2].map(|index| {
+                let vertex = &triangle[index];
+                let position = vertex.position();
+
+                ClipVertex {
+                    clip: mvp * Vec4::new(position.x, position.y, position.z, 1.0),
+                    position,
+                    normal: vertex.normal(),
+                    light_position: (light_mvp
+                        * Vec4::new(position.x, position.y, position.z, 1.0))
+                    .xyz(),
+                }
+            });
+
+            self.draw_clipped_triangle(clip_vertices, shading);
+        }
+    }
+
+    /// Clip a triangle against the near plane, then rasterize the remaining polygon. The far plane
+    /// and screen edges are handled during rasterization.
+    fn draw_clipped_triangle(&mut self, triangle: [ClipVertex; 3], shading: &Shading) {
+        if triangle
+            .iter()
+            .all(|vertex| vertex.near_plane_distance() >= 0.0)
+        {
+            self.rasterize(triangle.map(|vertex| self.to_screen(&vertex)), shading);
+            return;
+        }
+
+        let mut polygon = Vec::with_capacity(4);
+        for index in 0..3 {
+            let current = &triangle[index];
+            let next = &triangle[(index + 1) % 3];
+
+            let current_distance = current.near_plane_distance();
+            let next_distance = next.near_plane_distance();
+
+            if current_distance >= 0.0 {
+                polygon.push(*current);
+            }
+
+            if (current_distance >= 0.0) != (next_distance >= 0.0) {
+                let t = current_distance / (current_distance - next_distance);
+                polygon.push(current.lerp(next, t));
+            }
+        }
+
+        if polygon.len() < 3 {
+            return;
+        }
+
+        let screen_polygon: Vec<ScreenVertex> = polygon
+            .iter()
+            .map(|vertex| self.to_screen(vertex))
+            .collect();
+
+        for index in 1..screen_polygon.len() - 1 {
+            self.rasterize(
+                [
+                    screen_polygon[0],
+                    screen_polygon[index],
+                    screen_polygon[index + 1],
+                ],
+                shading,
+            );
+        }
+    }
+
+    fn to_screen(&self, vertex: &ClipVertex) -> ScreenVertex {
+        let inverse_w = 1.0 / vertex.clip.w;
+        let ndc = vertex.clip.xyz() * inverse_w;
+
+        // Image rows are stored top to bottom, so the y axis is flipped compared to OpenGL
+        ScreenVertex {
+            x: (ndc.x + 1.0) * 0.5 * self.color.width() as f32,
+            y: (1.0 - ndc.y) * 0.5 * self.color.height() as f32,
+            depth: ndc.z * 0.5 + 0.5,
+            inverse_w,
+            position: vertex.position,
+            normal: vertex.normal,
+            light_position: vertex.light_position,
+        }
+    }
+
+    fn rasterize(&mut self, [a, b, c]: [ScreenVertex; 3], shading: &Shading) {
+        let area = edge_function(&a, &b, c.x, c.y);
+        if area.abs() < f32::EPSILON {
+            return;
+        }
+
+        let width = self.color.width();
+        let height = self.color.height();
+
+        let min_x = a.x.min(b.x).min(c.x).floor().max(0.0) as u32;
+        let min_y = a.y.min(b.y).min(c.y).floor().max(0.0) as u32;
+        let max_x = (a.x.max(b.x).max(c.x).ceil().max(0.0) as u32).min(width);
+        let max_y = (a.y.max(b.y).max(c.y).ceil().max(0.0) as u32).min(height);
+
+        for y in min_y..max_y {
+            let sample_y = y as f32 + 0.5;
+
+            for x in min_x..max_x {
+                let sample_x = x as f32 + 0.5;
+
+                // Barycentric coordinates, normalized so the winding order does not matter
+                let weight_a = edge_function(&b, &c, sample_x, sample_y) / area;
+                let weight_b = edge_function(&c, &a, sample_x, sample_y) / area;
+                let weight_c = edge_function(&a, &b, sample_x, sample_y) / area;
+
+                if weight_a < 0.0 || weight_b < 0.0 || weight_c < 0.0 {
+                    continue;
+                }
+
+                let depth = weight_a * a.depth + weight_b * b.depth + weight_c * c.depth;
+                let depth_index = y as usize * width as usize + x as usize;
+                if !(0.0..1.0).contains(&depth) || depth >= self.depth[depth_index] {
+                    continue;
+                }
+
+                // Perspective correct interpolation of the vertex attributes
+                let perspective_a = weight_a * a.inverse_w;
+                let perspective_b = weight_b * b.inverse_w;
+                let perspective_c = weight_c * c.inverse_w;
+                let normalization = 1.0 / (perspective_a + perspective_b + perspective_c);
+
+                let position = (a.position * perspective_a
+                    + b.position * perspective_b
+                    + c.position * perspective_c)
+                    * normalization;
+                let normal = (a.normal * perspective_a
+                    + b.normal * perspective_b
+                    + c.normal * perspective_c)
+                    * normalization;
+                let light_position = (a.light_position * perspective_a
+                    + b.light_position * perspective_b
+                    + c.light_position * perspective_c)
+                    * normalization;
+
+                let mut color = shading.shade(position, normal, light_position);
+                if shading.material.is_translucent() {
+                    // Blend over the existing pixel, as with glBlendFunc(ONE, ONE_MINUS_SRC_ALPHA)
+                    let Rgba(destination) = *self.color.get_pixel(x, y);
+                    let destination = Vec4::from(destination.map(|x| x as f32 / 255.0));
+                    color += destination * (1.0 - color.w);
+                } else {
+                    self.depth[depth_index] = depth;
+                }
+
+                self.color.put_pixel(x, y, Rgba(color.map(unorm8).into()));
+            }
+        }
+    }
+}
+
+#[inline]
+fn edge_function(a: &ScreenVertex, b: &ScreenVertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::parse_color;
+
+    #[test]
+    fn renders_readme_blueprint() {
+        let blueprint = Blueprint::read_from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/blueprints/readme.txt"
+        ))
+        .unwrap();
+        let mut model_loader =
+            ModelLoader::new(concat!(env!("CARGO_MANIFEST_DIR"), "/models")).unwrap();
+
+        let background = parse_color("#ff00ff").unwrap();
+        let options = RenderOptions {
+            width: 160,
+            height: 90,
+            background,
+            ground_plane: false,
+            ..RenderOptions::default()
+        };
+        let img = SoftwareRenderer::new()
+            .render(&blueprint, &mut model_loader, &options)
+            .unwrap();
+
+        assert_eq!(img.dimensions(), (160, 90));
+
+        // The blueprint is framed in the middle of the image, leaving the edges empty
+        let background = Rgba([255, 0, 255, 255]);
+        let drawn: Vec<(u32, u32)> = img
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel != background)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(drawn.len() > 160 * 90 / 8);
+
+        let min_x = drawn.iter().map(|&(x, _)| x).min().unwrap();
+        let max_x = drawn.iter().map(|&(x, _)| x).max().unwrap();
+        let min_y = drawn.iter().map(|&(_, y)| y).min().unwrap();
+        let max_y = drawn.iter().map(|&(_, y)| y).max().unwrap();
+        assert!(0 < min_x && min_x < 80 && 80 < max_x && max_x < 159);
+        assert!(0 < min_y && min_y < 45 && 45 < max_y && max_y < 89);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::c_str;
+use crate::error::Result;
+use crate::render::gl::types::{GLenum, GLint, GLsizei, GLuint};
+use crate::render::shader::build_program;
+use crate::render::util::check_for_errors;
+use crate::render::{gl, Gl};
+use log::info;
+use nalgebra_glm::{inverse, Mat4, Vec3};
+use std::f32::consts::TAU;
+
+/// The number of samples taken around each point. This must match `ssao_frag.glsl`.
+const KERNEL_SIZE: usize = 32;
+
+/// Get the points sampled around each surface, within a hemisphere of radius 1 facing along +z.
+/// Points are spread evenly using low discrepancy sequences, so renders are reproducible, and are
+/// placed closer to the surface more often as nearby geometry matters most.
+fn sample_kernel() -> [Vec3; KERNEL_SIZE] {
+    /// The radical inverse of `index` in the given base, as used by the Halton sequence
+    fn radical_inverse(mut index: usize, base: usize) -> f32 {
+        let mut result = 0.0;
+        let mut fraction = 1.0 / base as f32;
+        while index > 0 {
+            result += (index % base) as f32 * fraction;
+            index /= base;
+            fraction /= base as f32;
+        }
+        result
+    }
+
+    std::array::from_fn(|index| {
+        // Keep samples away from the surface plane, where flat surfaces would occlude themselves
+        let cos_theta = 1.0 - 0.9 * (index as f32 + 0.5) / KERNEL_SIZE as f32;
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = TAU * radical_inverse(index, 2);
+
+        let direction = Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta);
+        let distance = radical_inverse(index, 3);
+        direction * (0.1 + 0.9 * distance * distance)
+    })
+}
+
+/// A texture which can be drawn to through its own framebuffer
+struct TextureTarget {
+    framebuffer: GLuint,
+    texture: GLuint,
+}
+
+impl TextureTarget {
+    unsafe fn new(
+        gl: &Gl,
+        internal_format: GLenum,
+        format: GLenum,
+        data_type: GLenum,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let mut texture = 0;
+        gl.GenTextures(1, &mut texture);
+        gl.BindTexture(gl::TEXTURE_2D, texture);
+        gl.TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            internal_format as GLint,
+            width as GLsizei,
+            height as GLsizei,
+            0,
+            format,
+            data_type,
+            std::ptr::null(),
+        );
+
+        // Samples outside of the screen repeat the nearest edge
+        for (parameter, value) in [
+            (gl::TEXTURE_MIN_FILTER, gl::NEAREST),
+            (gl::TEXTURE_MAG_FILTER, gl::NEAREST),
+            (gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE),
+            (gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE),
+        ] {
+            gl.TexParameteri(gl::TEXTURE_2D, parameter, value as GLint);
+        }
+
+        let mut framebuffer = 0;
+        gl.GenFramebuffers(1, &mut framebuffer);
+        gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+        gl.FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            texture,
+            0,
+        );
+
+        TextureTarget {
+            framebuffer,
+            texture,
+        }
+    }
+
+    unsafe fn delete(&self, gl: &Gl) {
+        gl.DeleteFramebuffers(1, &self.framebuffer);
+        gl.DeleteTextures(1, &self.texture);
+    }
+}
+
+/// The textures used to find the ambient occlusion of a render. These match the size of the
+/// render, so the result can be read at the position of each fragment.
+pub struct AmbientOcclusionTargets {
+    pub width: u32,
+    pub height: u32,
+    /// The view space normal and distance from the camera of the nearest opaque surface
+    g_buffer: TextureTarget,
+    g_buffer_depth: GLuint,
+    /// The fraction of samples around each point which are occluded
+    occlusion: TextureTarget,
+    /// The occlusion after the noise of the rotated kernel has been blurred out
+    blurred: TextureTarget,
+}
+
+impl AmbientOcclusionTargets {
+    pub unsafe fn new(gl: &Gl, width: u32, height: u32) -> Result<Self> {
+        info!(
+            "Creating ambient occlusion buffers of size ({}, {})",
+            width, height
+        );
+
+        let g_buffer = TextureTarget::new(gl, gl::RGBA32F, gl::RGBA, gl::FLOAT, width, height);
+
+        let mut g_buffer_depth = 0;
+        gl.GenRenderbuffers(1, &mut g_buffer_depth);
+        gl.BindRenderbuffer(gl::RENDERBUFFER, g_buffer_depth);
+        gl.RenderbufferStorage(
+            gl::RENDERBUFFER,
+            gl::DEPTH_COMPONENT24,
+            width as GLsizei,
+            height as GLsizei,
+        );
+        gl.FramebufferRenderbuffer(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_ATTACHMENT,
+            gl::RENDERBUFFER,
+            g_buffer_depth,
+        );
+
+        let occlusion = TextureTarget::new(gl, gl::R8, gl::RED, gl::UNSIGNED_BYTE, width, height);
+        let blurred = TextureTarget::new(gl, gl::R8, gl::RED, gl::UNSIGNED_BYTE, width, height);
+
+        check_for_errors(gl)?;
+        Ok(AmbientOcclusionTargets {
+            width,
+            height,
+            g_buffer,
+            g_buffer_depth,
+            occlusion,
+            blurred,
+        })
+    }
+
+    /// Release the graphics memory held by these buffers.
+    pub unsafe fn delete(self, gl: &Gl) {
+        self.g_buffer.delete(gl);
+        gl.DeleteRenderbuffers(1, &self.g_buffer_depth);
+        self.occlusion.delete(gl);
+        self.blurred.delete(gl);
+    }
+
+    /// Bind the G-buffer as the target for the following draw calls and clear it.
+    pub unsafe fn bind_g_buffer(&self, gl: &Gl) {
+        gl.BindFramebuffer(gl::FRAMEBUFFER, self.g_buffer.framebuffer);
+        gl.Viewport(0, 0, self.width as GLsizei, self.height as GLsizei);
+        gl.ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    }
+
+    /// Bind the blurred ambient occlusion to a texture unit so it can be sampled.
+    pub unsafe fn bind_texture(&self, gl: &Gl, unit: GLuint) {
+        gl.ActiveTexture(gl::TEXTURE0 + unit);
+        gl.BindTexture(gl::TEXTURE_2D, self.blurred.texture);
+    }
+}
+
+/// The programs used to draw the G-buffer and find the ambient occlusion from it
+pub struct AmbientOcclusionPrograms {
+    g_buffer: GLuint,
+    g_buffer_model: GLint,
+    g_buffer_view: GLint,
+    g_buffer_projection: GLint,
+    occlusion: GLuint,
+    occlusion_projection: GLint,
+    occlusion_inverse_projection: GLint,
+    occlusion_radius: GLint,
+    blur: GLuint,
+}
+
+impl AmbientOcclusionPrograms {
+    pub unsafe fn build(gl: &Gl) -> Result<Self> {
+        let screen_vert = c_str!(include_str!("../screen_vert.glsl"));
+
+        let g_buffer = build_program(
+            gl,
+            c_str!(include_str!("../gbuffer_vert.glsl")),
+            c_str!(include_str!("../gbuffer_frag.glsl")),
+        )?;
+        let occlusion = build_program(gl, screen_vert, c_str!(include_str!("../ssao_frag.glsl")))?;
+        let blur = build_program(
+            gl,
+            screen_vert,
+            c_str!(include_str!("../ssao_blur_frag.glsl")),
+        )?;
+
+        // The sample kernel never changes, so it is only uploaded once
+        gl.UseProgram(occlusion);
+        let kernel = sample_kernel();
+        gl.Uniform3fv(
+            gl.GetUniformLocation(occlusion, c_str!("kernel").as_ptr()),
+            KERNEL_SIZE as GLsizei,
+            kernel.as_ptr() as *const _,
+        );
+
+        let programs = AmbientOcclusionPrograms {
+            g_buffer,
+            g_buffer_model: gl.GetUniformLocation(g_buffer, c_str!("model").as_ptr()),
+            g_buffer_view: gl.GetUniformLocation(g_buffer, c_str!("view").as_ptr()),
+            g_buffer_projection: gl.GetUniformLocation(g_buffer, c_str!("projection").as_ptr()),
+            occlusion,
+            occlusion_projection: gl.GetUniformLocation(occlusion, c_str!("projection").as_ptr()),
+            occlusion_inverse_projection: gl
+                .GetUniformLocation(occlusion, c_str!("inverseProjection").as_ptr()),
+            occlusion_radius: gl.GetUniformLocation(occlusion, c_str!("radius").as_ptr()),
+            blur,
+        };
+
+        check_for_errors(gl)?;
+        Ok(programs)
+    }
+
+    /// Use the G-buffer program with the given camera for the following draw calls.
+    pub unsafe fn use_g_buffer(&self, gl: &Gl, view: &Mat4, projection: &Mat4) {
+        gl.UseProgram(self.g_buffer);
+        gl.UniformMatrix4fv(self.g_buffer_view, 1, gl::FALSE, view.as_ptr() as *const _);
+        gl.UniformMatrix4fv(
+            self.g_buffer_projection,
+            1,
+            gl::FALSE,
+            projection.as_ptr() as *const _,
+        );
+    }
+
+    pub unsafe fn set_g_buffer_model(&self, gl: &Gl, model: &Mat4) {
+        gl.UniformMatrix4fv(
+            self.g_buffer_model,
+            1,
+            gl::FALSE,
+            model.as_ptr() as *const _,
+        );
+    }
+
+    /// Find the ambient occlusion from a complete G-buffer, then blur the result. The vertex array
+    /// bound while drawing must not have any enabled attributes. The framebuffer of the blurred
+    /// occlusion is left bound, so the render target must be bound again afterwards.
+    pub unsafe fn draw_occlusion(
+        &self,
+        gl: &Gl,
+        targets: &AmbientOcclusionTargets,
+        projection: &Mat4,
+        radius: f32,
+    ) {
+        gl.Disable(gl::DEPTH_TEST);
+        gl.ActiveTexture(gl::TEXTURE0);
+
+        gl.UseProgram(self.occlusion);
+        gl.UniformMatrix4fv(
+            self.occlusion_projection,
+            1,
+            gl::FALSE,
+            projection.as_ptr() as *const _,
+        );
+        gl.UniformMatrix4fv(
+            self.occlusion_inverse_projection,
+            1,
+            gl::FALSE,
+            inverse(projection).as_ptr() as *const _,
+        );
+        gl.Uniform1f(self.occlusion_radius, radius);
+
+        gl.BindFramebuffer(gl::FRAMEBUFFER, targets.occlusion.framebuffer);
+        gl.BindTexture(gl::TEXTURE_2D, targets.g_buffer.texture);
+        gl.DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl.UseProgram(self.blur);
+        gl.BindFramebuffer(gl::FRAMEBUFFER, targets.blurred.framebuffer);
+        gl.BindTexture(gl::TEXTURE_2D, targets.occlusion.texture);
+        gl.DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl.Enable(gl::DEPTH_TEST);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::blueprint::{Blueprint, BlueprintEntry};
+use crate::error::Result;
+use crate::stats::BuildingFamily;
+use crate::tweaks::ModelLoader;
+use nalgebra_glm::{rotate_y, scale, translation, Mat4, Vec3};
+use std::collections::HashSet;
+
+/// A stand placed beneath part of a building
+pub struct StandPiece {
+    pub model: &'static str,
+    /// The world transform of the model
+    pub transform: Mat4,
+}
+
+/// Stands beneath most buildings, by the number of layers they reach down. The level 2 model is
+/// empty, so the stand used beneath belts is drawn in its place.
+const BUILDING_STANDS: [&str; 4] = [
+    "Stand_Level0Building",
+    "Stand_Level1Building",
+    "Stand_Level2Normal",
+    "Stand_Level3Building",
+];
+
+/// Stands beneath belts, lifts, splitters and mergers
+const BELT_STANDS: [&str; 4] = [
+    "Stand_Level0Normal",
+    "Stand_Level1Normal",
+    "Stand_Level2Normal",
+    "Stand_Level3Normal",
+];
+
+/// Stands beneath pipes
+const PIPE_STANDS: [&str; 4] = [
+    "PipeStand_Level0",
+    "PipeStand_Level1",
+    "PipeStand_Level2",
+    "PipeStand_Level3",
+];
+
+/// Stands beneath belt senders, which are made for up to two layers
+const BELT_PORT_SENDER_STANDS: [&str; 3] = [
+    "BeltPortSenderrStandsLevel0",
+    "BeltPortSenderrStandsLevel1",
+    "BeltPortSenderrStandsLevel2",
+];
+
+/// Stands beneath raised belt receivers, starting from one layer
+const BELT_PORT_RECEIVER_STANDS: [&str; 2] = [
+    "BeltPortReceiverStandsLevel1",
+    "BeltPortReceiverStandsLevel2",
+];
+
+/// Get the stand drawn beneath a building which reaches down a number of layers, if any
+fn stand_model(internal_name: &str, level: usize) -> Option<&'static str> {
+    let stands: &[&str] = if internal_name.starts_with("BeltPortSender") {
+        &BELT_PORT_SENDER_STANDS
+    } else if internal_name.starts_with("BeltPortReceiver") {
+        // Receivers rest on the floor when they are not raised
+        let index = level.checked_sub(1)?;
+        return Some(BELT_PORT_RECEIVER_STANDS[index.min(BELT_PORT_RECEIVER_STANDS.len() - 1)]);
+    } else if internal_name.starts_with("Lift") {
+        // `Lift_Stands` holds the braces of several lifts left where they were placed around the
+        // origin, spread over many tiles, so it can not be placed beneath a single lift tile
+        &BELT_STANDS
+    } else {
+        match BuildingFamily::for_building(internal_name) {
+            BuildingFamily::Belts => &BELT_STANDS,
+            BuildingFamily::Pipes => &PIPE_STANDS,
+            _ => &BUILDING_STANDS,
+        }
+    };
+
+    Some(stands[level.min(stands.len() - 1)])
+}
+
+/// Place the stands holding up each building. A stand is placed beneath every tile on the lowest
+/// layer of a building, reaching down past any empty layers to the building below it or to the
+/// ground. Buildings without a model are not given stands, so they are not left floating alone.
+pub fn support_stands(
+    blueprint: &Blueprint,
+    model_loader: &mut ModelLoader,
+) -> Result<Vec<StandPiece>> {
+    let occupied: HashSet<(i32, i32, i32)> = blueprint.iter().flat_map(|e| e.tiles()).collect();
+
+    let mut stands = Vec::new();
+    for entry in blueprint.iter() {
+        if model_loader.load_model(entry.internal_name())?.is_empty() {
+            continue;
+        }
+
+        let ((_, _, bottom), _) = entry.bounds();
+
+        for (x, y, layer) in entry.tiles() {
+            if layer != bottom {
+                continue;
+            }
+
+            let level = (0..layer)
+                .rev()
+                .take_while(|&below| !occupied.contains(&(x, y, below)))
+                .count();
+
+            if let Some(model) = stand_model(entry.internal_name(), level) {
+                stands.push(StandPiece {
+                    model,
+                    transform: stand_transform(entry, x, y, layer),
+                });
+            }
+        }
+    }
+    Ok(stands)
+}
+
+/// Place a stand at a tile covered by a building, turned the same way as the building
+fn stand_transform(entry: &BlueprintEntry, x: i32, y: i32, layer: i32) -> Mat4 {
+    let transform = translation(&Vec3::new(x as f32, layer as f32, y as f32));
+    let transform = scale(&transform, &Vec3::new(1.0, 1.0, -1.0));
+    rotate_y(&transform, entry.rotation())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the stand models placed beneath a blueprint, ordered by their position
+    fn stands(json: &str) -> Vec<(&'static str, (i32, i32, i32))> {
+        let blueprint = Blueprint::from_json(json.as_bytes()).unwrap();
+        let mut model_loader =
+            ModelLoader::new(concat!(env!("CARGO_MANIFEST_DIR"), "/models")).unwrap();
+
+        let mut stands: Vec<_> = support_stands(&blueprint, &mut model_loader)
+            .unwrap()
+            .into_iter()
+            .map(|stand| {
+                let position = stand.transform.column(3);
+                let position = (
+                    position.x.round() as i32,
+                    position.z.round() as i32,
+                    position.y.round() as i32,
+                );
+                (stand.model, position)
+            })
+            .collect();
+        stands.sort_by_key(|&(_, position)| position);
+        stands
+    }
+
+    #[test]
+    fn stands_reach_down_to_the_building_below() {
+        let json = r#"{"V":1122,"BP":{"Entries":[
+            {"X":0,"Y":0,"L":0,"T":"BeltDefaultForwardInternalVariant"},
+            {"X":0,"Y":0,"L":2,"T":"BeltDefaultForwardInternalVariant"},
+            {"X":1,"Y":0,"L":2,"T":"BeltDefaultForwardInternalVariant"},
+            {"X":2,"Y":0,"L":0,"T":"CutterDefaultInternalVariant"},
+            {"X":2,"Y":0,"L":1,"T":"CutterDefaultInternalVariant"}
+        ]}}"#;
+
+        assert_eq!(
+            stands(json),
+            [
+                ("Stand_Level0Normal", (0, 0, 0)),
+                ("Stand_Level1Normal", (0, 0, 2)),
+                ("Stand_Level2Normal", (1, 0, 2)),
+                ("Stand_Level0Building", (2, -1, 0)),
+                ("Stand_Level0Building", (2, -1, 1)),
+                ("Stand_Level0Building", (2, 0, 0)),
+                ("Stand_Level0Building", (2, 0, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn stands_are_placed_beneath_the_lowest_layer_of_a_building() {
+        let json = r#"{"V":1122,"BP":{"Entries":[
+            {"X":0,"Y":0,"L":1,"T":"Lift1UpForwardInternalVariant"},
+            {"X":1,"Y":0,"L":2,"T":"Lift1DownForwardInternalVariant"},
+            {"X":2,"Y":0,"L":3,"T":"PipeForwardInternalVariant"},
+            {"X":3,"Y":0,"L":3,"T":"BeltPortReceiverInternalVariant"},
+            {"X":4,"Y":0,"L":0,"T":"BeltPortReceiverInternalVariant"}
+        ]}}"#;
+
+        assert_eq!(
+            stands(json),
+            [
+                ("Stand_Level1Normal", (0, 0, 1)),
+                ("Stand_Level1Normal", (1, 0, 1)),
+                ("PipeStand_Level3", (2, 0, 3)),
+                ("BeltPortReceiverStandsLevel2", (3, 0, 3)),
+            ]
+        );
+    }
+}
@@ -1,25 +1,30 @@
+use crate::error::{Error, Result};
 use crate::render::gl::types::{GLenum, GLsizeiptr, GLuint};
 use crate::render::{gl, Gl};
 use std::ffi::c_void;
-use std::mem::size_of;
+use std::fmt::{Display, Formatter};
+use std::mem::size_of_val;
 
 pub unsafe fn load_vbo<T>(gl: &Gl, buffer: &[T]) -> GLuint {
     let mut vbo = 0;
     gl.GenBuffers(1, &mut vbo);
+    upload_vbo(gl, vbo, buffer);
+    vbo
+}
+
+/// Replace the contents of an existing vertex buffer.
+pub unsafe fn upload_vbo<T>(gl: &Gl, vbo: GLuint, buffer: &[T]) {
     gl.BindBuffer(gl::ARRAY_BUFFER, vbo);
 
     gl.BufferData(
         gl::ARRAY_BUFFER,
-        (size_of::<T>() * buffer.len()) as GLsizeiptr,
+        size_of_val(buffer) as GLsizeiptr,
         buffer.as_ptr() as *const c_void,
         gl::STATIC_DRAW,
     );
-
-    vbo
 }
 
-#[track_caller]
-pub fn check_for_errors(gl: &Gl) {
+pub fn check_for_errors(gl: &Gl) -> Result<()> {
     // Don't allocate memory on the heap unless we find an error (equivalent to Vec::new)
     let mut error_queue = Vec::with_capacity(0);
 
@@ -28,15 +33,14 @@ pub fn check_for_errors(gl: &Gl) {
     }
 
     if !error_queue.is_empty() {
-        panic!(
-            "OpenGL error queue contained contained errors: {:?}",
-            error_queue
-        )
+        return Err(Error::Gl(error_queue));
     }
+
+    Ok(())
 }
 
 #[derive(Copy, Clone, Debug)]
-enum GlError {
+pub enum GlError {
     InvalidEnum,
     InvalidValue,
     InvalidOperation,
@@ -48,6 +52,22 @@ enum GlError {
     UnknownErrorCode(GLenum),
 }
 
+impl Display for GlError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GlError::InvalidEnum => write!(f, "GL_INVALID_ENUM"),
+            GlError::InvalidValue => write!(f, "GL_INVALID_VALUE"),
+            GlError::InvalidOperation => write!(f, "GL_INVALID_OPERATION"),
+            GlError::StackOverflow => write!(f, "GL_STACK_OVERFLOW"),
+            GlError::StackUnderflow => write!(f, "GL_STACK_UNDERFLOW"),
+            GlError::OutOfMemory => write!(f, "GL_OUT_OF_MEMORY"),
+            GlError::InvalidFramebufferOperation => write!(f, "GL_INVALID_FRAMEBUFFER_OPERATION"),
+            GlError::ContextLost => write!(f, "GL_CONTEXT_LOST"),
+            GlError::UnknownErrorCode(code) => write!(f, "unknown error code {:#x}", code),
+        }
+    }
+}
+
 #[inline]
 unsafe fn get_gl_error(gl: &Gl) -> Option<GlError> {
     match gl.GetError() {
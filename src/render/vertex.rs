@@ -20,6 +20,14 @@ impl Vertex {
         }
     }
 
+    pub fn position(&self) -> Vec3 {
+        Vec3::from(self.position)
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        Vec3::from(self.normal)
+    }
+
     pub unsafe fn configure_vao(gl: &Gl) {
         gl.VertexAttribPointer(
             0,
@@ -0,0 +1,10 @@
+#version 330 core
+
+out vec2 TexCoord;
+
+void main() {
+    // A single triangle covering the entire screen, drawn without any vertex buffer
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    TexCoord = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,436 @@
+use crate::blueprint::Blueprint;
+use crate::error::{Error, Result};
+use crate::output::encode_image;
+use crate::render::{parse_point, CameraOverrides, RenderOptions, RenderOverrides, Renderer};
+use crate::tweaks::ModelLoader;
+use clap::ValueEnum;
+use image::ImageFormat;
+use log::{info, warn};
+use serde::Serialize;
+use std::io::Read;
+use std::str::FromStr;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// The largest blueprint accepted by the server
+const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The number of render requests which may wait for the renderer before new requests are rejected
+const MAX_QUEUED_RENDERS: usize = 64;
+
+/// Statistics reported by the `/stats` endpoint
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ServerStats {
+    pub uptime_secs: f64,
+    pub requests: u64,
+    pub renders_succeeded: u64,
+    pub renders_failed: u64,
+    pub queued_renders: usize,
+    pub total_render_ms: f64,
+    pub average_render_ms: f64,
+    pub models_resolved: usize,
+    pub models_requested: usize,
+}
+
+/// A request to render a blueprint, handed from the HTTP thread to the render thread once its body
+/// has been read
+struct RenderRequest {
+    request: Request,
+    blueprint: Vec<u8>,
+    options: RenderOptions,
+    format: ImageFormat,
+}
+
+/// An HTTP server which renders blueprints posted to it.
+///
+/// Requests are accepted on a background thread, while every render is performed on the thread
+/// calling [`RenderServer::run`]. This allows renderers which are not [`Send`] (such as
+/// [`GlRenderer`](crate::GlRenderer)) to be used safely.
+pub struct RenderServer<'r> {
+    server: Arc<Server>,
+    renderer: &'r mut dyn Renderer,
+    model_loader: ModelLoader,
+    defaults: RenderOptions,
+    max_size: u32,
+    stats: Arc<Mutex<ServerStats>>,
+}
+
+impl<'r> RenderServer<'r> {
+    /// Bind a server to the given address (such as `127.0.0.1:8080`). Requests for renders which
+    /// are larger than `max_size` on either side, including SSAA, are rejected.
+    pub fn bind(
+        address: &str,
+        renderer: &'r mut dyn Renderer,
+        model_loader: ModelLoader,
+        defaults: RenderOptions,
+        max_size: u32,
+    ) -> Result<Self> {
+        let server = Server::http(address).map_err(|source| Error::Bind {
+            address: address.to_string(),
+            source,
+        })?;
+
+        Ok(RenderServer {
+            server: Arc::new(server),
+            renderer,
+            model_loader,
+            defaults,
+            max_size,
+            stats: Arc::new(Mutex::new(ServerStats::default())),
+        })
+    }
+
+    /// Handle requests until the server is shut down. The body of each render request is read on
+    /// its own thread, so slow clients do not hold up other requests. The following endpoints are
+    /// served:
+    ///
+    ///  - `POST /render`: Render the blueprint string held in the request body. The query
+    ///    parameters `width`, `height`, `ssaa`, `format`, `background`, `ground`, `style`,
+    ///    `shadows`, `shadow_quality`, `ssao`, `ssao_strength`, `ssao_radius`, `view`, `yaw`,
+    ///    `pitch`, `fov`, `padding`, `distance`, `target` and `projection` override the default
+    ///    render options.
+    ///  - `GET /health`: Respond with `ok` once the server is accepting requests.
+    ///  - `GET /stats`: Get a JSON summary of the requests handled so far.
+    pub fn run(&mut self) -> Result<()> {
+        let start_time = Instant::now();
+        let (sender, receiver) = sync_channel(MAX_QUEUED_RENDERS);
+
+        let server = self.server.clone();
+        let stats = self.stats.clone();
+        let defaults = self.defaults;
+        let max_size = self.max_size;
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                stats.lock().unwrap().requests += 1;
+
+                if let Some(render) =
+                    accept_request(request, &defaults, max_size, &stats, start_time)
+                {
+                    let sender = sender.clone();
+                    let stats = stats.clone();
+                    thread::spawn(move || queue_render(render, &sender, &stats));
+                }
+            }
+        });
+
+        self.render_requests(receiver);
+        Ok(())
+    }
+
+    fn render_requests(&mut self, receiver: Receiver<RenderRequest>) {
+        for mut render in receiver {
+            let render_start_time = Instant::now();
+            let result = self.render(&mut render);
+            let render_time = render_start_time.elapsed();
+
+            let mut stats = self.stats.lock().unwrap();
+            stats.queued_renders -= 1;
+            (stats.models_resolved, stats.models_requested) = self.model_loader.load_counts();
+
+            match result {
+                Ok(image) => {
+                    stats.renders_succeeded += 1;
+                    stats.total_render_ms += render_time.as_secs_f64() * 1000.0;
+                    stats.average_render_ms =
+                        stats.total_render_ms / stats.renders_succeeded as f64;
+                    drop(stats);
+
+                    info!("Completed render request in {:?}", render_time);
+                    let content_type =
+                        Header::from_bytes("Content-Type", render.format.to_mime_type())
+                            .expect("mime types are valid header values");
+                    respond(
+                        render.request,
+                        Response::from_data(image).with_header(content_type),
+                    );
+                }
+                Err(err) => {
+                    stats.renders_failed += 1;
+                    drop(stats);
+
+                    warn!("Render request failed: {}", err);
+                    let status = match err {
+                        Error::Decode(_) | Error::InvalidOption(_) => 400,
+                        _ => 500,
+                    };
+                    respond_with_text(render.request, status, &err.to_string());
+                }
+            }
+        }
+    }
+
+    fn render(&mut self, render: &mut RenderRequest) -> Result<Vec<u8>> {
+        let blueprint = Blueprint::decode_bytes(std::mem::take(&mut render.blueprint))?;
+        let img = self
+            .renderer
+            .render(&blueprint, &mut self.model_loader, &render.options)?;
+        encode_image(img, render.format)
+    }
+}
+
+/// Respond to any request which does not require the renderer. If the request is a valid render
+/// request, it is returned so its body can be read and it can be queued.
+fn accept_request(
+    request: Request,
+    defaults: &RenderOptions,
+    max_size: u32,
+    stats: &Mutex<ServerStats>,
+    start_time: Instant,
+) -> Option<RenderRequest> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    match (request.method(), path) {
+        (Method::Get, "/health") => respond_with_text(request, 200, "ok"),
+        (Method::Get, "/stats") => {
+            let mut stats = stats.lock().unwrap().clone();
+            stats.uptime_secs = start_time.elapsed().as_secs_f64();
+
+            let body = serde_json::to_vec(&stats).expect("stats can always be serialized");
+            let content_type = Header::from_bytes("Content-Type", "application/json")
+                .expect("mime types are valid header values");
+            respond(request, Response::from_data(body).with_header(content_type));
+        }
+        (Method::Post, "/render") => {
+            let (options, format) = match parse_query(query, defaults, max_size) {
+                Ok(x) => x,
+                Err(msg) => {
+                    respond_with_text(request, 400, &msg);
+                    return None;
+                }
+            };
+
+            return Some(RenderRequest {
+                request,
+                blueprint: Vec::new(),
+                options,
+                format,
+            });
+        }
+        (_, "/health" | "/stats" | "/render") => {
+            respond_with_text(request, 405, "Method not allowed")
+        }
+        _ => respond_with_text(request, 404, "Not found"),
+    }
+
+    None
+}
+
+/// Read the blueprint held in the body of a render request and queue the request for the renderer.
+fn queue_render(
+    mut render: RenderRequest,
+    sender: &SyncSender<RenderRequest>,
+    stats: &Mutex<ServerStats>,
+) {
+    // Read one byte past the limit so oversized bodies are rejected rather than truncated
+    if let Err(err) = render
+        .request
+        .as_reader()
+        .take(MAX_BODY_SIZE + 1)
+        .read_to_end(&mut render.blueprint)
+    {
+        respond_with_text(
+            render.request,
+            400,
+            &format!("Failed to read request body: {}", err),
+        );
+        return;
+    }
+    if render.blueprint.len() as u64 > MAX_BODY_SIZE {
+        respond_with_text(
+            render.request,
+            413,
+            &format!("Request body is larger than {} bytes", MAX_BODY_SIZE),
+        );
+        return;
+    }
+
+    stats.lock().unwrap().queued_renders += 1;
+
+    if let Err(TrySendError::Full(render) | TrySendError::Disconnected(render)) =
+        sender.try_send(render)
+    {
+        stats.lock().unwrap().queued_renders -= 1;
+        respond_with_text(render.request, 503, "Render queue is full");
+    }
+}
+
+/// Apply the query parameters of a render request to the default options, rejecting renders which
+/// are larger than `max_size` on either side.
+fn parse_query(
+    query: &str,
+    defaults: &RenderOptions,
+    max_size: u32,
+) -> std::result::Result<(RenderOptions, ImageFormat), String> {
+    let mut overrides = RenderOverrides::default();
+    let mut camera = CameraOverrides::default();
+    let mut format = ImageFormat::Png;
+
+    let params = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key)?, percent_decode(value)?))
+        })
+        .collect::<std::result::Result<Vec<_>, String>>()?;
+
+    for (key, value) in &params {
+        let (key, value) = (key.as_str(), value.as_str());
+        match key {
+            "width" => overrides.width = Some(parse_param(key, value)?),
+            "height" => overrides.height = Some(parse_param(key, value)?),
+            "ssaa" => overrides.ssaa = Some(parse_param(key, value)?),
+            "background" => overrides.background = Some(value.to_string()),
+            "ground" => overrides.ground_plane = Some(parse_param(key, value)?),
+            "style" => overrides.style = Some(parse_value(key, value)?),
+            "shadows" => overrides.shadows = Some(parse_param(key, value)?),
+            "shadow_quality" => overrides.shadow_quality = Some(parse_value(key, value)?),
+            "ssao" => overrides.ssao = Some(parse_param(key, value)?),
+            "ssao_strength" => overrides.ssao_strength = Some(parse_param(key, value)?),
+            "ssao_radius" => overrides.ssao_radius = Some(parse_param(key, value)?),
+            "view" => camera.view = Some(parse_value(key, value)?),
+            "yaw" => camera.yaw = Some(parse_param(key, value)?),
+            "pitch" => camera.pitch = Some(parse_param(key, value)?),
+            "fov" => camera.fov = Some(parse_param(key, value)?),
+            "padding" => camera.padding = Some(parse_param(key, value)?),
+            "distance" => camera.distance = Some(parse_param(key, value)?),
+            "target" => camera.target = Some(parse_point(value)?),
+            "projection" => camera.projection = Some(parse_value(key, value)?),
+            "format" => {
+                format = ImageFormat::from_extension(value)
+                    .filter(ImageFormat::can_write)
+                    .ok_or_else(|| format!("Unsupported image format: {}", value))?;
+            }
+            _ => return Err(format!("Unknown query parameter: {}", key)),
+        }
+    }
+    overrides.camera = Some(camera);
+
+    let options = overrides.apply(defaults).map_err(|err| err.to_string())?;
+    options
+        .check_size(max_size)
+        .map_err(|err| err.to_string())?;
+    Ok((options, format))
+}
+
+fn parse_param<T: FromStr>(key: &str, value: &str) -> std::result::Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for query parameter {}: {}", key, value))
+}
+
+/// Parse a query parameter naming one of a set of values, accepting the same names as the command
+/// line.
+fn parse_value<T: ValueEnum>(key: &str, value: &str) -> std::result::Result<T, String> {
+    T::from_str(value, false)
+        .map_err(|_| format!("Invalid value for query parameter {}: {}", key, value))
+}
+
+/// Decode a component of a URL query, where `+` stands for a space and `%` is followed by the two
+/// hexadecimal digits of an encoded byte.
+fn percent_decode(text: &str) -> std::result::Result<String, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut remaining = text.bytes();
+
+    while let Some(byte) = remaining.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex_digit = |digit: Option<u8>| digit.and_then(|d| (d as char).to_digit(16));
+                match (hex_digit(remaining.next()), hex_digit(remaining.next())) {
+                    (Some(high), Some(low)) => bytes.push((high * 16 + low) as u8),
+                    _ => return Err(format!("Invalid escape in query: {}", text)),
+                }
+            }
+            _ => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| format!("Query is not valid UTF-8: {}", text))
+}
+
+fn respond_with_text(request: Request, status: u16, text: &str) {
+    respond(
+        request,
+        Response::from_string(text).with_status_code(status),
+    );
+}
+
+fn respond<R: Read>(request: Request, response: Response<R>) {
+    if let Err(err) = request.respond(response) {
+        warn!("Failed to send response: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::RenderStyle;
+    use nalgebra_glm::{Vec3, Vec4};
+
+    #[test]
+    fn percent_decode_escapes() {
+        assert_eq!(percent_decode("%23ff0000").unwrap(), "#ff0000");
+        assert_eq!(percent_decode("%2c%2C").unwrap(), ",,");
+        assert_eq!(percent_decode("%E2%9C%93").unwrap(), "\u{2713}");
+        assert_eq!(percent_decode("a+b%20c").unwrap(), "a b c");
+        assert_eq!(percent_decode("%2B").unwrap(), "+");
+    }
+
+    #[test]
+    fn percent_decode_rejects_bad_escapes() {
+        for text in ["%", "%2", "%zz", "%2g", "abc%"] {
+            assert!(percent_decode(text).is_err(), "{} was accepted", text);
+        }
+        assert!(percent_decode("%ff").is_err());
+    }
+
+    #[test]
+    fn parse_query_overrides_defaults() {
+        let defaults = RenderOptions::default();
+        let (options, format) = parse_query(
+            "width=640&height=480&background=%2300ff00&style=realistic&target=1%2C2%2C3&format=jpg",
+            &defaults,
+            4096,
+        )
+        .unwrap();
+
+        assert_eq!((options.width, options.height), (640, 480));
+        assert_eq!(options.background, Vec4::new(0.0, 1.0, 0.0, 1.0));
+        assert_eq!(options.style, RenderStyle::Realistic);
+        assert_eq!(options.camera.target, Some(Vec3::new(1.0, 2.0, 3.0)));
+        assert_eq!(options.ssaa, defaults.ssaa);
+        assert_eq!(format, ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn parse_query_uses_last_repeated_key() {
+        let defaults = RenderOptions::default();
+        let (options, _) = parse_query("width=100&&width=200", &defaults, 4096).unwrap();
+        assert_eq!(options.width, 200);
+    }
+
+    #[test]
+    fn parse_query_rejects_bad_parameters() {
+        let defaults = RenderOptions::default();
+        for query in [
+            "size=100",
+            "width=wide",
+            "width=%zz",
+            "style=sketch",
+            "format=txt",
+            "width=0",
+            "width=4000&ssaa=2",
+        ] {
+            assert!(
+                parse_query(query, &defaults, 4096).is_err(),
+                "{} was accepted",
+                query
+            );
+        }
+    }
+}
@@ -0,0 +1,19 @@
+#version 330 core
+layout (location = 0) out float Occlusion;
+
+uniform sampler2D occlusion;
+
+void main() {
+    // Average over the same 4x4 tile used to rotate the sample kernel
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    ivec2 maxPixel = textureSize(occlusion, 0) - 1;
+
+    float total = 0.0;
+    for (int x = -2; x < 2; x++) {
+        for (int y = -2; y < 2; y++) {
+            total += texelFetch(occlusion, clamp(pixel + ivec2(x, y), ivec2(0), maxPixel), 0).r;
+        }
+    }
+
+    Occlusion = total / 16.0;
+}
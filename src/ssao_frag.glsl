@@ -0,0 +1,72 @@
+#version 330 core
+layout (location = 0) out float Occlusion;
+
+in vec2 TexCoord;
+
+const int KERNEL_SIZE = 32;
+
+// The distance a surface must be in front of a sample before it occludes it, so flat surfaces do
+// not occlude themselves
+const float BIAS = 0.02;
+
+uniform sampler2D gBuffer;
+uniform mat4 projection;
+uniform mat4 inverseProjection;
+uniform vec3 kernel[KERNEL_SIZE];
+uniform float radius;
+
+// Get the view space position of the point on the screen at the given distance from the camera
+vec3 viewPosition(vec2 uv, float depth) {
+    vec4 near = inverseProjection * vec4(uv * 2.0 - 1.0, -1.0, 1.0);
+    vec4 far = inverseProjection * vec4(uv * 2.0 - 1.0, 1.0, 1.0);
+    near.xyz /= near.w;
+    far.xyz /= far.w;
+
+    return mix(near.xyz, far.xyz, (-depth - near.z) / (far.z - near.z));
+}
+
+void main() {
+    vec4 surface = texture(gBuffer, TexCoord);
+
+    // Nothing was drawn here
+    if (surface.w == 0.0) {
+        Occlusion = 0.0;
+        return;
+    }
+
+    vec3 position = viewPosition(TexCoord, surface.w);
+    vec3 normal = surface.xyz;
+
+    // Rotate the kernel by a different angle for each pixel within a 4x4 tile. The pattern this
+    // leaves is removed by the blur pass.
+    ivec2 pixel = ivec2(gl_FragCoord.xy) % 4;
+    float angle = float((pixel.x * 4 + pixel.y) * 7 % 16) / 16.0 * 6.28318530718;
+    vec3 rotation = vec3(cos(angle), sin(angle), 0.0);
+
+    vec3 tangent = rotation - normal * dot(rotation, normal);
+    if (dot(tangent, tangent) < 1e-4) {
+        tangent = cross(normal, vec3(0.0, 0.0, 1.0));
+    }
+    tangent = normalize(tangent);
+    mat3 tbn = mat3(tangent, cross(normal, tangent), normal);
+
+    float occlusion = 0.0;
+    for (int i = 0; i < KERNEL_SIZE; i++) {
+        vec3 samplePosition = position + tbn * kernel[i] * radius;
+
+        vec4 clip = projection * vec4(samplePosition, 1.0);
+        vec2 uv = clip.xy / clip.w * 0.5 + 0.5;
+
+        float sceneDepth = texture(gBuffer, uv).w;
+        if (sceneDepth == 0.0) {
+            continue;
+        }
+
+        // Surfaces far in front of this one, such as the edge of a building closer to the camera,
+        // do not occlude it
+        float rangeCheck = smoothstep(0.0, 1.0, radius / abs(surface.w - sceneDepth));
+        occlusion += (sceneDepth <= -samplePosition.z - BIAS ? 1.0 : 0.0) * rangeCheck;
+    }
+
+    Occlusion = occlusion / float(KERNEL_SIZE);
+}
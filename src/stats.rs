@@ -0,0 +1,302 @@
+use crate::blueprint::Blueprint;
+use crate::error::Result;
+use crate::tweaks::ModelLoader;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Broad groups of buildings, used to summarize what a blueprint is made of
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildingFamily {
+    /// Belts, lifts, splitters, mergers and other buildings which move shapes
+    Belts,
+    /// Pipes, pumps and other buildings which move fluids
+    Pipes,
+    /// Buildings which change shapes, such as cutters, rotators, stackers and painters
+    Processing,
+    /// Shape and fluid storage
+    Storage,
+    /// Wires, gates and other buildings which work with signals
+    Logic,
+    /// Sandbox buildings which produce shapes and fluids from nothing
+    Sandbox,
+    /// Labels and any building which is not recognized
+    Other,
+}
+
+impl BuildingFamily {
+    /// Get the family of a building from its internal name.
+    pub fn for_building(internal_name: &str) -> Self {
+        // Checked in order, so longer prefixes must come before any prefix they start with
+        const PREFIXES: [(&str, BuildingFamily); 27] = [
+            ("Sandbox", BuildingFamily::Sandbox),
+            ("FluidStorage", BuildingFamily::Storage),
+            ("Storage", BuildingFamily::Storage),
+            ("Belt", BuildingFamily::Belts),
+            ("Lift", BuildingFamily::Belts),
+            ("Splitter", BuildingFamily::Belts),
+            ("Merger", BuildingFamily::Belts),
+            ("Tunnel", BuildingFamily::Belts),
+            ("Pipe", BuildingFamily::Pipes),
+            ("Pump", BuildingFamily::Pipes),
+            ("Cutter", BuildingFamily::Processing),
+            ("Rotator", BuildingFamily::Processing),
+            ("Stacker", BuildingFamily::Processing),
+            ("Unstacker", BuildingFamily::Processing),
+            ("Swapper", BuildingFamily::Processing),
+            ("Painter", BuildingFamily::Processing),
+            ("Mixer", BuildingFamily::Processing),
+            ("PinPusher", BuildingFamily::Processing),
+            ("Crystal", BuildingFamily::Processing),
+            ("Extractor", BuildingFamily::Processing),
+            ("Trash", BuildingFamily::Processing),
+            ("Wire", BuildingFamily::Logic),
+            ("Gate", BuildingFamily::Logic),
+            ("ConstantSignal", BuildingFamily::Logic),
+            ("Button", BuildingFamily::Logic),
+            ("Display", BuildingFamily::Logic),
+            ("Filter", BuildingFamily::Logic),
+        ];
+
+        PREFIXES
+            .into_iter()
+            .find(|(prefix, _)| internal_name.starts_with(prefix))
+            .map_or(BuildingFamily::Other, |(_, family)| family)
+    }
+}
+
+impl Display for BuildingFamily {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BuildingFamily::Belts => "belts",
+            BuildingFamily::Pipes => "pipes",
+            BuildingFamily::Processing => "processing",
+            BuildingFamily::Storage => "storage",
+            BuildingFamily::Logic => "logic",
+            BuildingFamily::Sandbox => "sandbox",
+            BuildingFamily::Other => "other",
+        })
+    }
+}
+
+/// The area covered by the buildings on a single layer, including buildings which extend onto the
+/// layer from another layer
+#[derive(Clone, Debug, Serialize)]
+pub struct LayerStats {
+    pub layer: i32,
+    /// The number of buildings placed on this layer
+    pub buildings: usize,
+    /// The smallest x and y tile covered on this layer
+    pub min: [i32; 2],
+    /// The largest x and y tile covered on this layer
+    pub max: [i32; 2],
+    /// The number of tiles along the x axis
+    pub width: i32,
+    /// The number of tiles along the y axis
+    pub depth: i32,
+}
+
+/// How many of the buildings in a blueprint have a model that can be drawn
+#[derive(Clone, Debug, Serialize)]
+pub struct ModelStats {
+    /// The number of distinct buildings with at least one model
+    pub resolved: usize,
+    /// The distinct buildings without any model
+    pub missing: Vec<String>,
+}
+
+/// A summary of the buildings within a blueprint
+#[derive(Clone, Debug, Serialize)]
+pub struct BlueprintStats {
+    pub version: i32,
+    pub buildings: usize,
+    pub islands: usize,
+    pub by_name: BTreeMap<String, usize>,
+    pub by_family: BTreeMap<BuildingFamily, usize>,
+    /// Every occupied layer, from lowest to highest
+    pub layers: Vec<LayerStats>,
+    pub models: ModelStats,
+    pub estimated_cost: u64,
+}
+
+impl BlueprintStats {
+    /// Summarize a blueprint. The models for each building are resolved through the loader, in
+    /// the same way as when the blueprint is rendered.
+    pub fn new(blueprint: &Blueprint, model_loader: &mut ModelLoader) -> Result<Self> {
+        let mut by_name = BTreeMap::new();
+        let mut by_family = BTreeMap::new();
+        let mut layers: BTreeMap<i32, LayerStats> = BTreeMap::new();
+
+        for entry in blueprint.iter() {
+            *by_name
+                .entry(entry.internal_name().to_string())
+                .or_default() += 1;
+            *by_family
+                .entry(BuildingFamily::for_building(entry.internal_name()))
+                .or_default() += 1;
+
+            let (_, _, anchor_layer) = entry.tile();
+            for (x, y, layer) in entry.tiles() {
+                let stats = layers.entry(layer).or_insert(LayerStats {
+                    layer,
+                    buildings: 0,
+                    min: [x, y],
+                    max: [x, y],
+                    width: 0,
+                    depth: 0,
+                });
+                stats.min = [stats.min[0].min(x), stats.min[1].min(y)];
+                stats.max = [stats.max[0].max(x), stats.max[1].max(y)];
+            }
+            layers
+                .get_mut(&anchor_layer)
+                .expect("footprints cover their anchor tile")
+                .buildings += 1;
+        }
+
+        for stats in layers.values_mut() {
+            stats.width = stats.max[0] - stats.min[0] + 1;
+            stats.depth = stats.max[1] - stats.min[1] + 1;
+        }
+
+        let mut models = ModelStats {
+            resolved: 0,
+            missing: Vec::new(),
+        };
+        for name in by_name.keys() {
+            match model_loader.load_model(name)?.is_empty() {
+                false => models.resolved += 1,
+                true => models.missing.push(name.clone()),
+            }
+        }
+
+        Ok(BlueprintStats {
+            version: blueprint.version(),
+            buildings: blueprint.len(),
+            islands: blueprint.islands().len(),
+            by_name,
+            by_family,
+            layers: layers.into_values().collect(),
+            models,
+            estimated_cost: estimate_cost(blueprint.len()),
+        })
+    }
+}
+
+/// Estimate the cost of placing a blueprint with the given number of buildings. The cost of a
+/// blueprint grows faster than the number of buildings within it, and is estimated here as
+/// `(n - 1)^1.3` rounded up, so a single building is free.
+pub fn estimate_cost(buildings: usize) -> u64 {
+    match buildings {
+        0 | 1 => 0,
+        n => ((n - 1) as f64).powf(1.3).ceil() as u64,
+    }
+}
+
+impl Display for BlueprintStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Version: {}", self.version)?;
+        if self.islands > 0 {
+            writeln!(f, "Islands: {}", self.islands)?;
+        }
+        writeln!(f, "Buildings: {}", self.buildings)?;
+        writeln!(f, "Estimated cost: {}", self.estimated_cost)?;
+
+        writeln!(f, "\nLayers:")?;
+        for layer in &self.layers {
+            writeln!(
+                f,
+                "  {:>2}  {:>5} buildings  {} x {} tiles from ({}, {}) to ({}, {})",
+                layer.layer,
+                layer.buildings,
+                layer.width,
+                layer.depth,
+                layer.min[0],
+                layer.min[1],
+                layer.max[0],
+                layer.max[1]
+            )?;
+        }
+
+        writeln!(f, "\nFamilies:")?;
+        for (family, count) in &self.by_family {
+            writeln!(f, "  {:<12} {:>5}", family.to_string(), count)?;
+        }
+
+        let name_width = self.by_name.keys().map(String::len).max().unwrap_or(0);
+        writeln!(f, "\nBuildings:")?;
+        for (name, count) in &self.by_name {
+            writeln!(f, "  {:<name_width$} {:>5}", name, count)?;
+        }
+
+        write!(
+            f,
+            "\nModels: {} of {} kinds of building resolved",
+            self.models.resolved,
+            self.by_name.len()
+        )?;
+        if !self.models.missing.is_empty() {
+            write!(f, ", missing {}", self.models.missing.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_cost_grows_faster_than_buildings() {
+        assert_eq!(estimate_cost(0), 0);
+        assert_eq!(estimate_cost(1), 0);
+        assert_eq!(estimate_cost(2), 1);
+        assert_eq!(estimate_cost(3), 3);
+        assert_eq!(estimate_cost(11), 20);
+        assert_eq!(estimate_cost(210), 1038);
+    }
+
+    #[test]
+    fn summarizes_readme_blueprint() {
+        let blueprint = Blueprint::read_from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/blueprints/readme.txt"
+        ))
+        .unwrap();
+        let mut model_loader =
+            ModelLoader::new(concat!(env!("CARGO_MANIFEST_DIR"), "/models")).unwrap();
+        let stats = BlueprintStats::new(&blueprint, &mut model_loader).unwrap();
+
+        assert_eq!(stats.buildings, 210);
+        assert_eq!(stats.islands, 0);
+        assert_eq!(stats.estimated_cost, estimate_cost(210));
+        assert_eq!(
+            stats.by_family,
+            BTreeMap::from([
+                (BuildingFamily::Belts, 134),
+                (BuildingFamily::Processing, 76)
+            ])
+        );
+
+        let layers: Vec<_> = stats
+            .layers
+            .iter()
+            .map(|layer| (layer.layer, layer.buildings, layer.min, layer.max))
+            .collect();
+        assert_eq!(
+            layers,
+            [(0, 166, [-6, -8], [5, 9]), (1, 44, [-6, -7], [5, 5])]
+        );
+        assert_eq!((stats.layers[0].width, stats.layers[0].depth), (12, 18));
+        assert_eq!((stats.layers[1].width, stats.layers[1].depth), (12, 13));
+        assert_eq!(
+            stats
+                .layers
+                .iter()
+                .map(|layer| layer.buildings)
+                .sum::<usize>(),
+            stats.buildings
+        );
+    }
+}
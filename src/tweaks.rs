@@ -1,99 +1,144 @@
-use log::{error, warn};
-use nalgebra_glm::Vec3;
+use crate::error::{Error, Result};
+use crate::mapping::{find_object_file, MappedModel, ModelMapping};
+use log::warn;
+use nalgebra_glm::{Mat4, Vec3};
 use obj::Obj;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 pub struct ModelLoader {
-    resolved_objects: HashMap<String, Rc<Obj>>,
+    resolved_objects: HashMap<PathBuf, Rc<Obj>>,
     model_sets: HashMap<String, Vec<Model>>,
     model_dir: PathBuf,
+    mapping: ModelMapping,
 }
 
 pub struct Model {
     pub model: Rc<Obj>,
-    pub offset: Vec3,
+    /// The transform from the space of the model file into the space of the building
+    pub transform: Mat4,
+    /// The color of the model, if it differs from the default blueprint color
+    pub color: Option<Vec3>,
+    /// The name of the material used to draw the model in the realistic style. Unless the mapping
+    /// names a material, this is the name of the model file.
+    pub material: String,
 }
 
 impl ModelLoader {
-    pub fn new<P: AsRef<Path>>(model_dir: P) -> Self {
-        ModelLoader {
+    /// Create a loader for a model directory, using the built-in mapping extended by any mapping
+    /// file within the directory.
+    pub fn new<P: AsRef<Path>>(model_dir: P) -> Result<Self> {
+        let mapping = ModelMapping::for_model_dir(&model_dir)?;
+        Self::with_mapping(model_dir, mapping)
+    }
+
+    /// Create a loader for a model directory which uses the given mapping. A warning is logged for
+    /// each model referenced by the mapping which does not exist.
+    pub fn with_mapping<P: AsRef<Path>>(model_dir: P, mapping: ModelMapping) -> Result<Self> {
+        if !model_dir.as_ref().is_dir() {
+            return Err(Error::ModelDirectory(model_dir.as_ref().to_path_buf()));
+        }
+
+        for missing in mapping.missing_models(&model_dir) {
+            warn!(
+                "Model mapping for {} references missing file {}.obj",
+                missing.building, missing.file
+            );
+        }
+
+        Ok(ModelLoader {
             resolved_objects: HashMap::new(),
             model_sets: HashMap::new(),
             model_dir: model_dir.as_ref().to_path_buf(),
-        }
+            mapping,
+        })
     }
 
-    fn try_load_object<P: AsRef<Path>>(path: P) -> Option<Obj> {
-        if !path.as_ref().is_file() {
-            return None;
-        }
+    pub fn mapping(&self) -> &ModelMapping {
+        &self.mapping
+    }
 
-        let mut obj = match Obj::load(path.as_ref()) {
-            Ok(obj) => obj,
-            Err(e) => {
-                error!("Failed to read model {}: {}", path.as_ref().display(), e);
-                return None;
-            }
-        };
+    /// Check whether a building is known, either by being listed in the mapping or by having a
+    /// model file matching its name. This does not load any models.
+    pub fn has_model(&self, name: &str) -> bool {
+        self.mapping.get(name).is_some() || find_object_file(&self.model_dir, name).is_some()
+    }
 
-        if let Err(e) = obj.load_mtls() {
-            error!("Error: failed to load model materials: {}", e);
-            return None;
+    fn try_load_object<P: AsRef<Path>>(path: P) -> Result<Option<Obj>> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Ok(None);
         }
 
-        Some(obj)
+        let mut obj = Obj::load(path).map_err(|err| Error::ModelLoad {
+            path: path.to_path_buf(),
+            source: Box::new(err),
+        })?;
+
+        obj.load_mtls().map_err(|err| Error::ModelLoad {
+            path: path.to_path_buf(),
+            source: Box::new(err),
+        })?;
+
+        Ok(Some(obj))
     }
 
-    fn find_object(&mut self, mut name: &str) -> Option<Rc<Obj>> {
-        if let Some(obj) = self.resolved_objects.get(name) {
-            return Some(obj.clone());
+    fn find_object(&mut self, name: &str) -> Result<Option<Rc<Obj>>> {
+        let Some(path) = find_object_file(&self.model_dir, name) else {
+            return Ok(None);
+        };
+
+        if let Some(obj) = self.resolved_objects.get(&path) {
+            return Ok(Some(obj.clone()));
         }
 
-        'search: loop {
-            if let Some(obj) = Self::try_load_object(self.model_dir.join(format!("{}.obj", name))) {
+        match Self::try_load_object(&path)? {
+            Some(obj) => {
                 let reference_counted = Rc::new(obj);
                 self.resolved_objects
-                    .insert(name.to_owned(), reference_counted.clone());
-                return Some(reference_counted);
+                    .insert(path, reference_counted.clone());
+                Ok(Some(reference_counted))
             }
-
-            for suffix in ["InternalVariant", "Default"] {
-                if let Some(remaining) = name.strip_suffix(suffix) {
-                    name = remaining;
-                    continue 'search;
-                }
-            }
-
-            return None;
+            None => Ok(None),
         }
     }
 
-    pub fn load_model(&mut self, name: &str) -> &[Model] {
-        self.ensure_model_loaded(name);
-        &self.model_sets.get(name).unwrap()[..]
+    pub fn load_model(&mut self, name: &str) -> Result<&[Model]> {
+        self.ensure_model_loaded(name)?;
+        Ok(&self.model_sets[name][..])
     }
 
-    fn ensure_model_loaded(&mut self, name: &str) {
+    fn ensure_model_loaded(&mut self, name: &str) -> Result<()> {
         if self.model_sets.contains_key(name) {
-            return;
+            return Ok(());
         }
 
-        let model_set = match internal_name_mapping_adjustments(name) {
-            None => Vec::from_iter(self.find_object(name).map(|model| Model {
-                model,
-                offset: Vec3::default(),
-            })),
+        let mut model_set = Vec::new();
+        match self.mapping.get(name).map(<[MappedModel]>::to_vec) {
+            None => {
+                if let Some(model) = self.find_object(name)? {
+                    model_set.push(Model {
+                        model,
+                        transform: Mat4::identity(),
+                        color: None,
+                        material: name.to_owned(),
+                    });
+                }
+            }
             Some(mappings) => {
-                Vec::from_iter(mappings.iter().filter_map(|&Mapping { file, offset }| {
-                    Some(Model {
-                        model: self.find_object(file)?,
-                        offset,
-                    })
-                }))
+                for mapped in mappings {
+                    if let Some(model) = self.find_object(&mapped.file)? {
+                        model_set.push(Model {
+                            model,
+                            transform: mapped.transform(),
+                            color: mapped.color,
+                            material: mapped.material.unwrap_or(mapped.file),
+                        });
+                    }
+                }
             }
-        };
+        }
 
         if model_set.is_empty() {
             warn!(
@@ -103,6 +148,7 @@ impl ModelLoader {
         }
 
         self.model_sets.insert(name.to_owned(), model_set);
+        Ok(())
     }
 
     pub fn load_counts(&self) -> (usize, usize) {
@@ -114,105 +160,3 @@ impl ModelLoader {
         (resolved, self.model_sets.len())
     }
 }
-
-#[derive(Copy, Clone)]
-pub struct Mapping<'s> {
-    file: &'s str,
-    offset: Vec3,
-}
-
-impl<'s> Mapping<'s> {
-    const fn new(file: &'s str, offset: Vec3) -> Self {
-        Mapping { file, offset }
-    }
-
-    const fn redirect(file: &'s str) -> Self {
-        Mapping {
-            file,
-            offset: Vec3::new(0.0, 0.0, 0.0),
-        }
-    }
-}
-
-macro_rules! const_mapping {
-    ($($token:tt)*) => {{
-        const X: &[Mapping] = &[$($token)*];
-        X
-    }};
-}
-
-fn internal_name_mapping_adjustments(internal_name: &str) -> Option<&'static [Mapping<'static>]> {
-    Some(match internal_name {
-        //belts
-        "BeltDefaultForwardInternalVariant" => const_mapping![Mapping::redirect("Belt_Straight")],
-        "BeltDefaultRightInternalVariant" => const_mapping![Mapping::redirect("Belt_90_R")],
-        "BeltDefaultLeftInternalVariant" => const_mapping![Mapping::redirect("Belt_90_L")],
-        //vertical
-        "Lift1UpBackwardInternalVariant" => const_mapping![Mapping::redirect("Lift1UpBackwards")],
-        //belts special
-        "SplitterTShapeInternalVariant" => const_mapping![Mapping::redirect("Splitter2to1T")],
-        "MergerTShapeInternalVariant" => const_mapping![Mapping::redirect("Merger2to1T")],
-        "BeltPortSenderInternalVariant" => const_mapping![Mapping::redirect("BeltPortSender")],
-        "BeltPortReceiverInternalVariant" => const_mapping![Mapping::redirect("BeltPortReceiver")],
-
-        //rotating
-        "RotatorOneQuadInternalVariant" => const_mapping![
-            Mapping::redirect("Rotator1Quad"),
-            Mapping::new("Rotator1QuadPlatform90CC", Vec3::new(0.0, 0.05, 0.0))
-        ],
-        "RotatorOneQuadCCWInternalVariant" => const_mapping![
-            Mapping::redirect("Rotator1Quad"),
-            Mapping::new("Rotator1QuadPlatform90CW", Vec3::new(0.0, 0.05, 0.0))
-        ],
-        "RotatorHalfInternalVariant" => const_mapping![
-            Mapping::redirect("Rotator1Quad"),
-            Mapping::new("Rotator1QuadPlatform180", Vec3::new(0.0, 0.05, 0.0))
-        ],
-
-        //processing
-        "CutterDefaultInternalVariant" => const_mapping![Mapping::redirect("CutterStatic_Fixed")],
-        "StackerDefaultInternalVariant" => const_mapping![Mapping::redirect("StackerSolid")],
-        "PainterDefaultInternalVariant" => const_mapping![
-            Mapping::redirect("PainterBasin"),
-            Mapping::new("PainterRoll", Vec3::new(0.0, 0.3, 0.0))
-        ],
-        "MixerDefaultInternalVariant" => const_mapping![Mapping::redirect("MixerFoundation")],
-        "CutterHalfInternalVariant" => const_mapping![Mapping::redirect("HalfCutter")],
-        "PinPusherDefaultInternalVariant" => const_mapping![
-            Mapping::redirect("PinPusher"),
-            Mapping::redirect("PinPusherRotator1"),
-            Mapping::new("PinPusherClampR", Vec3::new(0.0, 0.16, 0.0)),
-            Mapping::new("PinPusherClampL", Vec3::new(0.0, 0.15, 0.0))
-        ],
-
-        //pipes normal
-        "PipeForwardInternalVariant" => const_mapping![Mapping::redirect("PipeForwardGlas")],
-        "PipeLeftInternalVariant" => const_mapping![Mapping::redirect("PipeLeftGlas")],
-        "PipeRightInternalVariant" => const_mapping![Mapping::redirect("PipeRightGlas")],
-        "PipeCrossInternalVariant" => const_mapping![Mapping::redirect("PipeCrossJunctionGlas")],
-        "PipeJunctionInternalVariant" => const_mapping![Mapping::redirect("PipeJunctionGlas")],
-        //pipes up
-        "PipeUpForwardInternalVariant" => const_mapping![Mapping::redirect("Pipe1UpForwardGlas")],
-        "PipeUpBackwardInternalVariant" => const_mapping![Mapping::redirect("Pipe1UpBackwardGlas")],
-        "PipeUpLeftInternalVariant" => const_mapping![Mapping::redirect("Pipe1UpLeftBlueprint")],
-        "PipeUpRightInternalVariant" => const_mapping![Mapping::redirect("Pipe1UpRightBlueprint")],
-        //pipes down
-        "PipeDownForwardInternalVariant" => const_mapping![Mapping::redirect("Pipe1DownGlas")],
-        "PipeDownBackwardInternalVariant" => {
-            const_mapping![Mapping::redirect("Pipe1DownBackwardGlas")]
-        }
-        "PipeDownRightInternalVariant" => const_mapping![Mapping::redirect("Pipe1DownRightGlas")],
-        "PipeDownLeftInternalVariant" => const_mapping![Mapping::redirect("Pipe1DownLeftGlas")],
-
-        // Support Buildings
-        "LabelDefaultInternalVariant" => const_mapping![Mapping::redirect("LabelSupport")],
-        "FluidStorageDefaultInternalVariant" => {
-            const_mapping![Mapping::redirect("PaintTankFoundation")]
-        }
-        "StorageDefaultInternalVariant" => const_mapping![Mapping::redirect("StorageSolid")],
-        "SandboxFluidProducerDefaultInternalVariant" => {
-            const_mapping![Mapping::redirect("SandboxIFluidProducer")]
-        }
-        _ => return None,
-    })
-}
@@ -0,0 +1,435 @@
+use crate::blueprint::{Blueprint, BlueprintContents, BlueprintEntry};
+use crate::tweaks::ModelLoader;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+
+/// The layers buildings may be placed on within the game
+pub const SUPPORTED_LAYERS: RangeInclusive<i32> = 0..=2;
+
+/// The blueprint versions which the renderer is known to read correctly
+pub const SUPPORTED_VERSIONS: RangeInclusive<i32> = 1000..=1122;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The blueprint may still be rendered, but may not look as expected
+    Warning,
+    /// The blueprint is not valid within the game
+    Error,
+}
+
+/// The kinds of problems found by the validator
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticCode {
+    /// Several buildings occupy the same tile and layer
+    Overlap,
+    /// A building has a rotation outside of 0 to 3
+    InvalidRotation,
+    /// A building covers a layer which does not exist within the game
+    InvalidLayer,
+    /// A building is not listed in the model mapping and has no model file
+    UnknownBuilding,
+    /// The blueprint was created by a version of the game which is not supported
+    UnsupportedVersion,
+}
+
+impl Display for DiagnosticCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DiagnosticCode::Overlap => "overlap",
+            DiagnosticCode::InvalidRotation => "invalid_rotation",
+            DiagnosticCode::InvalidLayer => "invalid_layer",
+            DiagnosticCode::UnknownBuilding => "unknown_building",
+            DiagnosticCode::UnsupportedVersion => "unsupported_version",
+        })
+    }
+}
+
+/// The location of an entry within a blueprint. For island blueprints, the index is that of the
+/// building within the entries of its island.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct EntryLocation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub island: Option<usize>,
+    pub index: usize,
+}
+
+impl Display for EntryLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.island {
+            Some(island) => write!(f, "{}:{}", island, self.index),
+            None => write!(f, "{}", self.index),
+        }
+    }
+}
+
+/// A problem found within a blueprint
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub message: String,
+    /// The entries involved in the problem, empty for problems with the blueprint as a whole
+    pub entries: Vec<EntryLocation>,
+}
+
+/// Every problem found within a blueprint
+#[derive(Clone, Debug, Serialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationReport {
+    pub fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+
+    /// Whether the blueprint passes validation. Warnings are only treated as failures when
+    /// `strict` is set.
+    pub fn passes(&self, strict: bool) -> bool {
+        self.valid && !(strict && self.warnings() > 0)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for diagnostic in &self.diagnostics {
+            let severity = match diagnostic.severity {
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            };
+            write!(f, "{}[{}]", severity, diagnostic.code)?;
+
+            let entries: Vec<String> = diagnostic.entries.iter().map(ToString::to_string).collect();
+            match entries.len() {
+                0 => {}
+                1 => write!(f, " entry {}", entries[0])?,
+                _ => write!(f, " entries {}", entries.join(", "))?,
+            }
+            writeln!(f, ": {}", diagnostic.message)?;
+        }
+
+        write!(
+            f,
+            "Found {} errors and {} warnings",
+            self.errors(),
+            self.warnings()
+        )
+    }
+}
+
+/// Check a blueprint for problems which would stop it from being used within the game or from
+/// being rendered correctly. A blueprint is valid when no errors are found, although it may still
+/// have warnings.
+pub fn validate(blueprint: &Blueprint, model_loader: &ModelLoader) -> ValidationReport {
+    let mut diagnostics = Vec::new();
+
+    if !SUPPORTED_VERSIONS.contains(&blueprint.version()) {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            code: DiagnosticCode::UnsupportedVersion,
+            message: format!(
+                "blueprint version {} is outside of the supported versions {} to {}",
+                blueprint.version(),
+                SUPPORTED_VERSIONS.start(),
+                SUPPORTED_VERSIONS.end()
+            ),
+            entries: Vec::new(),
+        });
+    }
+
+    // Each entry as it is stored, alongside the entry placed within the space of the blueprint
+    let entries: Vec<(EntryLocation, &BlueprintEntry, BlueprintEntry)> = match blueprint.contents()
+    {
+        BlueprintContents::Buildings(buildings) => buildings
+            .entries()
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let location = EntryLocation {
+                    island: None,
+                    index,
+                };
+                (location, entry, entry.clone())
+            })
+            .collect(),
+        BlueprintContents::Islands(_) => blueprint
+            .islands()
+            .iter()
+            .enumerate()
+            .flat_map(|(island_index, island)| {
+                island
+                    .buildings()
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, entry)| {
+                        let location = EntryLocation {
+                            island: Some(island_index),
+                            index,
+                        };
+                        (location, entry, island.place_building(entry))
+                    })
+            })
+            .collect(),
+    };
+
+    let mut unknown_buildings: BTreeMap<&str, Vec<EntryLocation>> = BTreeMap::new();
+    let mut tiles: BTreeMap<(i32, i32, i32), Vec<EntryLocation>> = BTreeMap::new();
+
+    for (location, entry, placed) in &entries {
+        if !(0..4).contains(&entry.raw_rotation()) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                code: DiagnosticCode::InvalidRotation,
+                message: format!(
+                    "{} has rotation {}, expected 0 to 3",
+                    entry.internal_name(),
+                    entry.raw_rotation()
+                ),
+                entries: vec![*location],
+            });
+        }
+
+        let ((_, _, min_layer), (_, _, max_layer)) = entry.bounds();
+        let layer = if min_layer < *SUPPORTED_LAYERS.start() {
+            min_layer
+        } else {
+            max_layer
+        };
+        if !SUPPORTED_LAYERS.contains(&layer) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                code: DiagnosticCode::InvalidLayer,
+                message: format!(
+                    "{} reaches layer {}, expected {} to {}",
+                    entry.internal_name(),
+                    layer,
+                    SUPPORTED_LAYERS.start(),
+                    SUPPORTED_LAYERS.end()
+                ),
+                entries: vec![*location],
+            });
+        }
+
+        if !model_loader.has_model(entry.internal_name()) {
+            unknown_buildings
+                .entry(entry.internal_name())
+                .or_default()
+                .push(*location);
+        }
+
+        for tile in placed.tiles() {
+            tiles.entry(tile).or_default().push(*location);
+        }
+    }
+
+    // Buildings overlapping on several tiles are reported once, at the first tile they share
+    let mut overlaps: BTreeMap<Vec<EntryLocation>, Vec<(i32, i32, i32)>> = BTreeMap::new();
+    for (tile, locations) in tiles {
+        if locations.len() > 1 {
+            overlaps.entry(locations).or_default().push(tile);
+        }
+    }
+
+    for (locations, shared_tiles) in overlaps {
+        let (x, y, layer) = shared_tiles[0];
+        let message = match shared_tiles.len() {
+            1 => format!(
+                "{} buildings occupy tile ({}, {}) on layer {}",
+                locations.len(),
+                x,
+                y,
+                layer
+            ),
+            count => format!(
+                "{} buildings occupy {} tiles, starting at ({}, {}) on layer {}",
+                locations.len(),
+                count,
+                x,
+                y,
+                layer
+            ),
+        };
+
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code: DiagnosticCode::Overlap,
+            message,
+            entries: locations,
+        });
+    }
+
+    for (name, locations) in unknown_buildings {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            code: DiagnosticCode::UnknownBuilding,
+            message: format!("{} has no mapping or model file", name),
+            entries: locations,
+        });
+    }
+
+    ValidationReport {
+        valid: !diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error),
+        diagnostics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BELT: &str = "BeltDefaultForwardInternalVariant";
+
+    fn validate_json(version: i32, entries: &str) -> ValidationReport {
+        let json = format!(r#"{{"V":{},"BP":{{"Entries":{}}}}}"#, version, entries);
+        let blueprint = Blueprint::from_json(json.as_bytes()).unwrap();
+        let model_loader =
+            ModelLoader::new(concat!(env!("CARGO_MANIFEST_DIR"), "/models")).unwrap();
+        validate(&blueprint, &model_loader)
+    }
+
+    fn codes(report: &ValidationReport) -> Vec<(Severity, DiagnosticCode)> {
+        report
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.code))
+            .collect()
+    }
+
+    fn location(index: usize) -> EntryLocation {
+        EntryLocation {
+            island: None,
+            index,
+        }
+    }
+
+    #[test]
+    fn accepts_valid_blueprint() {
+        let report = validate_json(
+            1122,
+            &format!(
+                r#"[{{"X":0,"Y":0,"T":"{BELT}"}},{{"X":1,"Y":0,"R":1,"T":"{BELT}"}},{{"X":0,"Y":0,"L":2,"T":"{BELT}"}}]"#
+            ),
+        );
+
+        assert!(report.valid);
+        assert!(report.diagnostics.is_empty());
+        assert!(report.passes(true));
+    }
+
+    #[test]
+    fn reports_overlapping_buildings() {
+        // The cutter covers (0, 0) and (0, -1), so only the second belt overlaps it
+        let report = validate_json(
+            1122,
+            &format!(
+                r#"[{{"X":0,"Y":0,"T":"CutterDefaultInternalVariant"}},{{"X":0,"Y":-1,"T":"{BELT}"}},{{"X":0,"Y":1,"T":"{BELT}"}}]"#
+            ),
+        );
+
+        assert!(!report.valid);
+        assert_eq!(codes(&report), [(Severity::Error, DiagnosticCode::Overlap)]);
+        assert_eq!(report.diagnostics[0].entries, [location(0), location(1)]);
+        assert_eq!(
+            report.diagnostics[0].message,
+            "2 buildings occupy tile (0, -1) on layer 0"
+        );
+    }
+
+    #[test]
+    fn reports_buildings_outside_of_supported_layers() {
+        let report = validate_json(
+            1122,
+            &format!(
+                r#"[{{"X":0,"Y":0,"L":3,"T":"{BELT}"}},{{"X":1,"Y":0,"L":-1,"T":"{BELT}"}},{{"X":2,"Y":0,"L":2,"T":"{BELT}"}}]"#
+            ),
+        );
+
+        assert!(!report.valid);
+        assert_eq!(
+            codes(&report),
+            [
+                (Severity::Error, DiagnosticCode::InvalidLayer),
+                (Severity::Error, DiagnosticCode::InvalidLayer)
+            ]
+        );
+        assert_eq!(report.diagnostics[0].entries, [location(0)]);
+        assert_eq!(report.diagnostics[1].entries, [location(1)]);
+    }
+
+    #[test]
+    fn reports_invalid_rotation() {
+        let report = validate_json(1122, &format!(r#"[{{"X":0,"Y":0,"R":4,"T":"{BELT}"}}]"#));
+
+        assert!(!report.valid);
+        assert_eq!(
+            codes(&report),
+            [(Severity::Error, DiagnosticCode::InvalidRotation)]
+        );
+    }
+
+    #[test]
+    fn warns_of_unknown_buildings() {
+        let report = validate_json(
+            1122,
+            r#"[{"X":0,"Y":0,"T":"NotABuilding"},{"X":1,"Y":0,"T":"NotABuilding"}]"#,
+        );
+
+        assert!(report.valid);
+        assert_eq!(
+            codes(&report),
+            [(Severity::Warning, DiagnosticCode::UnknownBuilding)]
+        );
+        assert_eq!(report.diagnostics[0].entries, [location(0), location(1)]);
+    }
+
+    #[test]
+    fn warns_of_unsupported_version() {
+        let entries = format!(r#"[{{"X":0,"Y":0,"T":"{BELT}"}}]"#);
+        for version in [SUPPORTED_VERSIONS.start() - 1, SUPPORTED_VERSIONS.end() + 1] {
+            let report = validate_json(version, &entries);
+
+            assert!(report.valid);
+            assert_eq!(
+                codes(&report),
+                [(Severity::Warning, DiagnosticCode::UnsupportedVersion)]
+            );
+            assert!(report.diagnostics[0].entries.is_empty());
+        }
+
+        assert!(validate_json(*SUPPORTED_VERSIONS.start(), &entries)
+            .diagnostics
+            .is_empty());
+    }
+
+    #[test]
+    fn strict_mode_fails_on_warnings() {
+        let report = validate_json(1, r#"[{"X":0,"Y":0,"T":"NotABuilding"}]"#);
+
+        assert!(report.valid);
+        assert_eq!((report.errors(), report.warnings()), (0, 2));
+        assert!(report.passes(false));
+        assert!(!report.passes(true));
+
+        let report = validate_json(1122, &format!(r#"[{{"X":0,"Y":0,"L":3,"T":"{BELT}"}}]"#));
+        assert!(!report.passes(false));
+        assert!(!report.passes(true));
+    }
+}
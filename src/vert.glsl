@@ -4,13 +4,16 @@ layout (location = 1) in vec3 normal;
 
 out vec3 Position;
 out vec3 Normal;
+out vec4 LightSpacePosition;
 
 uniform mat4 model;
 uniform mat4 view;
 uniform mat4 projection;
+uniform mat4 lightSpace;
 
 void main() {
     Position = position;
     Normal = normal;
+    LightSpacePosition = lightSpace * model * vec4(position, 1.0);
     gl_Position = projection * view * model * vec4(position, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,144 @@
+use crate::blueprint::Blueprint;
+use crate::error::Result;
+use crate::output::{encode_image, save_image};
+use crate::render::{RenderOptions, RenderOverrides, Renderer};
+use crate::tweaks::ModelLoader;
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use image::ImageFormat;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// A single render request read by the worker
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderJob {
+    /// An identifier which is echoed back in the result of this job
+    #[serde(default)]
+    pub id: Option<Value>,
+    /// The blueprint string to render
+    pub blueprint: String,
+    /// Changes to the default render options
+    #[serde(flatten)]
+    pub overrides: RenderOverrides,
+    /// The path to save the image to. If not provided, the image is returned in the result as a
+    /// base64 encoded PNG.
+    pub output: Option<PathBuf>,
+}
+
+/// The outcome of a single [`RenderJob`]
+#[derive(Debug, Default, Serialize)]
+pub struct JobResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    /// The base64 encoded PNG, when the job did not specify an output path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: f64,
+}
+
+/// Renders a stream of jobs while keeping the renderer and loaded models alive between them.
+pub struct Worker<'r> {
+    renderer: &'r mut dyn Renderer,
+    model_loader: ModelLoader,
+    defaults: RenderOptions,
+}
+
+impl<'r> Worker<'r> {
+    /// Create a worker which uses `defaults` for any option a job does not specify.
+    pub fn new(
+        renderer: &'r mut dyn Renderer,
+        model_loader: ModelLoader,
+        defaults: RenderOptions,
+    ) -> Self {
+        Worker {
+            renderer,
+            model_loader,
+            defaults,
+        }
+    }
+
+    /// Read newline delimited JSON jobs from `input` until it is closed, writing one line of JSON
+    /// to `output` for each job. Failed jobs are reported in their result; only IO errors on the
+    /// input or output stop the worker.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let result = match serde_json::from_str::<RenderJob>(&line) {
+                Ok(job) => self.run_job(job),
+                Err(err) => JobResult {
+                    error: Some(format!("Invalid render job: {}", err)),
+                    ..JobResult::default()
+                },
+            };
+
+            serde_json::to_writer(&mut output, &result).map_err(std::io::Error::from)?;
+            output.write_all(b"\n")?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Render a single job. Any error is reported through the returned result.
+    pub fn run_job(&mut self, job: RenderJob) -> JobResult {
+        let job_start_time = Instant::now();
+        let id = job.id.clone();
+        let output = job.output.clone();
+
+        let mut result = match self.try_run_job(job) {
+            Ok(image) => JobResult {
+                success: true,
+                output,
+                image,
+                ..JobResult::default()
+            },
+            Err(err) => {
+                warn!("Render job failed: {}", err);
+                JobResult {
+                    error: Some(err.to_string()),
+                    ..JobResult::default()
+                }
+            }
+        };
+
+        result.id = id;
+        result.duration_ms = job_start_time.elapsed().as_secs_f64() * 1000.0;
+        info!("Completed render job in {:?}", job_start_time.elapsed());
+        result
+    }
+
+    fn try_run_job(&mut self, job: RenderJob) -> Result<Option<String>> {
+        let blueprint = Blueprint::decode_bytes(job.blueprint.into_bytes())?;
+
+        let options = job.overrides.apply(&self.defaults)?;
+
+        let img = self
+            .renderer
+            .render(&blueprint, &mut self.model_loader, &options)?;
+
+        match job.output {
+            Some(path) => {
+                save_image(img, path)?;
+                Ok(None)
+            }
+            None => {
+                let png = encode_image(img, ImageFormat::Png)?;
+                Ok(Some(BASE64_STANDARD.encode(png)))
+            }
+        }
+    }
+}